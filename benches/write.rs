@@ -0,0 +1,93 @@
+extern crate criterion;
+extern crate futures;
+extern crate hyper;
+extern crate influent;
+extern crate tokio;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures::{future, Future, Stream};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use hyper::service::service_fn;
+use influent::client::{Client, Credentials, WriteOptions};
+use influent::client::http::HttpClient;
+use influent::hurl::hyper::{HyperHurl, HyperOptions};
+use influent::measurement::{Measurement, Value};
+use influent::serializer::line::LineSerializer;
+use std::net::{SocketAddr, TcpListener};
+use tokio::runtime::Runtime;
+use tokio::runtime::current_thread;
+
+const MEASUREMENTS: usize = 1000;
+const BATCH: u16 = 50;
+
+/// Starts server which accepts every write, returning its address.
+fn serve(rt: &mut Runtime) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    rt.spawn(future::lazy(move || {
+        Server::from_tcp(listener).unwrap().serve(|| {
+            // body is read before responding, otherwise server closes the connection
+            service_fn(|req: Request<Body>| {
+                req.into_body().concat2().map(|_| {
+                    let mut resp = Response::new(Body::empty());
+                    *resp.status_mut() = StatusCode::NO_CONTENT;
+                    resp
+                })
+            })
+        }).map_err(|e| panic!("server failed: {}", e))
+    }));
+
+    addr
+}
+
+fn client(addr: SocketAddr, keep_alive: bool) -> HttpClient {
+    let credentials = Credentials {
+        username: "gobwas",
+        password: "xxx",
+        database: "bench"
+    };
+
+    let hurl = HyperHurl::with_options(HyperOptions {
+        keep_alive,
+        ..HyperOptions::default()
+    }).unwrap();
+
+    let mut client = HttpClient::new(credentials, Box::new(LineSerializer::new()), Box::new(hurl));
+    client.add_host(&format!("http://{}", addr));
+    client.write_options = WriteOptions {
+        max_batch: Some(BATCH),
+        ..WriteOptions::default()
+    };
+
+    client
+}
+
+fn write_many(c: &mut Criterion) {
+    let mut server = Runtime::new().unwrap();
+    let addr = serve(&mut server);
+
+    let measurements: Vec<Measurement> = (0..MEASUREMENTS).map(|i| {
+        let mut measurement = Measurement::new("bench");
+        measurement.add_tag("host", "a");
+        measurement.add_field("value", Value::Integer(i as i64));
+        measurement
+    }).collect();
+
+    let mut rt = current_thread::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("write_many");
+    group.throughput(Throughput::Elements(MEASUREMENTS as u64));
+
+    for &(name, keep_alive) in &[("pooled", true), ("new connection per request", false)] {
+        let client = client(addr, keep_alive);
+
+        group.bench_function(name, |b| {
+            b.iter(|| rt.block_on(client.write_many(&measurements, None)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, write_many);
+criterion_main!(benches);
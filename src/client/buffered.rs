@@ -0,0 +1,406 @@
+use ::measurement::Measurement;
+use ::client::{ClientError, ClientReplayResult, WriteOptions};
+use ::client::http::HttpClient;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures::{Async, Future, Poll, Stream};
+use futures::stream::FuturesUnordered;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use tokio::timer::Interval;
+
+const MAX_COUNT: usize = 5000;
+const MAX_BYTES: usize = 1024 * 1024;
+const FLUSH_INTERVAL: u64 = 1;
+
+/// Options of the `BufferedWriter`.
+#[derive(Debug, Clone)]
+pub struct BufferOptions {
+    /// Number of buffered measurements which triggers flush.
+    pub max_count: usize,
+
+    /// Size of buffered measurements in bytes which triggers flush.
+    pub max_bytes: usize,
+
+    /// Interval of flushing buffered measurements, regardless of their number and size.
+    pub flush_interval: Duration,
+
+    /// Options of the write requests.
+    pub write_options: Option<WriteOptions>
+}
+
+impl Default for BufferOptions {
+    fn default() -> BufferOptions {
+        BufferOptions {
+            max_count: MAX_COUNT,
+            max_bytes: MAX_BYTES,
+            flush_interval: Duration::from_secs(FLUSH_INTERVAL),
+            write_options: None
+        }
+    }
+}
+
+impl BufferOptions {
+    /// Checks that options could be used by the worker.
+    pub fn validate(&self) -> Result<(), ClientError> {
+        if self.flush_interval == Duration::from_secs(0) {
+            return Err(ClientError::InvalidOptions("flush_interval must be greater than zero".to_string()));
+        }
+
+        match self.write_options {
+            Some(ref options) => options.validate(),
+            None => Ok(())
+        }
+    }
+}
+
+/// Result of a single flush.
+#[derive(Debug)]
+pub struct Flush {
+    /// Number of flushed measurements.
+    pub count: usize,
+
+    /// Size of flushed measurements in bytes.
+    pub bytes: usize,
+
+    /// Result of the write.
+    pub result: Result<(), ClientError>
+}
+
+enum Command {
+    Write(String),
+    Flush
+}
+
+/// Handle of the buffered writer.
+///
+/// Measurements written through the handle are serialized and buffered by the `Worker`, which
+/// writes them in batches with the `HttpClient`. Handle could be cloned and shared across tasks.
+#[derive(Clone)]
+pub struct BufferedWriter {
+    client: Arc<HttpClient>,
+    sender: UnboundedSender<Command>
+}
+
+impl BufferedWriter {
+    /// Constructs new writer handle and its worker.
+    ///
+    /// Worker is a future, which should be spawned on the tokio runtime. It resolves after all
+    /// handles are dropped and the rest of buffered measurements is flushed. Result of each flush
+    /// is passed to `on_flush`. Fails with `ClientError::InvalidOptions`, if options are invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate influent;
+    /// extern crate tokio;
+    ///
+    /// use influent::create_client;
+    /// use influent::client::Credentials;
+    /// use influent::client::buffered::{BufferedWriter, BufferOptions};
+    /// use influent::measurement::{Measurement, Value};
+    ///
+    /// # fn main() {
+    /// let credentials = Credentials {
+    ///     username: "gobwas",
+    ///     password: "xxx",
+    ///     database: "mydb"
+    /// };
+    ///
+    /// let client = create_client(credentials, vec!["http://localhost:8086"]).unwrap();
+    /// let (writer, worker) = BufferedWriter::new(client, BufferOptions::default(), |flush| {
+    ///     if let Err(e) = flush.result {
+    ///         println!("could not write {} measurements: {:?}", flush.count, e);
+    ///     }
+    /// }).unwrap();
+    ///
+    /// let mut measurement = Measurement::new("key");
+    /// measurement.add_field("field", Value::Integer(1));
+    ///
+    /// writer.write(&measurement).unwrap();
+    /// drop(writer);
+    ///
+    /// # if false {
+    /// tokio::run(worker);
+    /// # }
+    /// # }
+    /// ```
+    pub fn new<F>(client: HttpClient, options: BufferOptions, on_flush: F) -> Result<(BufferedWriter, Worker), ClientError> where F: FnMut(Flush) + Send + 'static {
+        options.validate()?;
+
+        let client = Arc::new(client);
+        let (sender, receiver) = unbounded();
+
+        let interval = Interval::new(Instant::now() + options.flush_interval, options.flush_interval);
+
+        let worker = Worker {
+            client: client.clone(),
+            options,
+            receiver,
+            interval,
+            on_flush: Box::new(on_flush),
+            buffer: Vec::new(),
+            bytes: 0,
+            flushes: FuturesUnordered::new(),
+            replay: None,
+            closed: false
+        };
+
+        let writer = BufferedWriter {
+            client,
+            sender
+        };
+
+        Ok((writer, worker))
+    }
+
+    /// Buffers measurement to be written.
+    pub fn write(&self, measurement: &Measurement) -> Result<(), ClientError> {
+        self.write_line(self.client.serialize(measurement))
+    }
+
+    /// Buffers already serialized measurement to be written.
+    pub fn write_line(&self, line: String) -> Result<(), ClientError> {
+        self.send(Command::Write(line))
+    }
+
+    /// Makes worker to flush buffered measurements.
+    pub fn flush(&self) -> Result<(), ClientError> {
+        self.send(Command::Flush)
+    }
+
+    fn send(&self, command: Command) -> Result<(), ClientError> {
+        self.sender.unbounded_send(command).map_err(|_| ClientError::Closed)
+    }
+}
+
+/// Worker of the `BufferedWriter`.
+///
+/// If the client has a spool, worker replays it on every flush interval tick.
+pub struct Worker {
+    client: Arc<HttpClient>,
+    options: BufferOptions,
+    receiver: UnboundedReceiver<Command>,
+    interval: Interval,
+    on_flush: Box<dyn FnMut(Flush) + Send>,
+    buffer: Vec<String>,
+    bytes: usize,
+    flushes: FuturesUnordered<Box<dyn Future<Item=Flush, Error=()> + Send>>,
+    replay: Option<ClientReplayResult>,
+    closed: bool
+}
+
+impl Worker {
+    fn push(&mut self, line: String) {
+        self.bytes += line.len();
+        self.buffer.push(line);
+
+        if self.buffer.len() >= self.options.max_count || self.bytes >= self.options.max_bytes {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let lines = mem::take(&mut self.buffer);
+        let bytes = mem::replace(&mut self.bytes, 0);
+        let count = lines.len();
+
+        let flush = self.client.write_lines(&lines, self.options.write_options.clone()).then(move |result| {
+            Ok(Flush {
+                count,
+                bytes,
+                result
+            })
+        });
+
+        self.flushes.push(Box::new(flush));
+    }
+}
+
+impl Future for Worker {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        while !self.closed {
+            match self.receiver.poll() {
+                Ok(Async::Ready(Some(Command::Write(line)))) => self.push(line),
+                Ok(Async::Ready(Some(Command::Flush))) => self.flush(),
+                Ok(Async::Ready(None)) | Err(_) => {
+                    self.closed = true;
+                    self.flush();
+                },
+                Ok(Async::NotReady) => break
+            }
+        }
+
+        if !self.closed {
+            while let Ok(Async::Ready(Some(_))) = self.interval.poll() {
+                self.flush();
+
+                if self.replay.is_none() {
+                    self.replay = Some(self.client.replay());
+                }
+            }
+        }
+
+        // failed replay is retried on the next tick
+        let replayed = match self.replay {
+            Some(ref mut replay) => !matches!(replay.poll(), Ok(Async::NotReady)),
+            None => false
+        };
+
+        if replayed || self.closed {
+            self.replay = None;
+        }
+
+        while let Ok(Async::Ready(Some(flush))) = self.flushes.poll() {
+            (self.on_flush)(flush);
+        }
+
+        if self.closed && self.flushes.is_empty() {
+            return Ok(Async::Ready(()));
+        }
+
+        Ok(Async::NotReady)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferedWriter, BufferOptions};
+    use ::client::{Credentials, ClientError};
+    use ::client::http::HttpClient;
+    use ::hurl::{Hurl, Request, Response, HurlResult};
+    use ::measurement::{Measurement, Value};
+    use ::serializer::line::LineSerializer;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use futures;
+    use tokio::runtime::current_thread::Runtime;
+    use tokio::timer::Delay;
+
+    struct MockHurl {
+        bodies: Arc<Mutex<Vec<String>>>
+    }
+
+    impl Hurl for MockHurl {
+        fn request(&self, req: Request) -> HurlResult {
+            self.bodies.lock().unwrap().push(req.body.unwrap());
+            Box::new(futures::future::ok(Response { status: 204, headers: HashMap::new(), body: "".to_string() }))
+        }
+    }
+
+    fn before(bodies: Arc<Mutex<Vec<String>>>) -> HttpClient {
+        let credentials = Credentials {
+            username: "gobwas",
+            password: "1234",
+            database: "test"
+        };
+
+        let mut client = HttpClient::new(credentials, Box::new(LineSerializer::new()), Box::new(MockHurl { bodies }));
+        client.add_host("http://localhost:8086");
+        client
+    }
+
+    fn measurement(i: i64) -> Measurement<'static> {
+        let mut measurement = Measurement::new("key");
+        measurement.add_field("i", Value::Integer(i));
+        measurement
+    }
+
+    #[test]
+    fn test_flush_by_count() {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let flushes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = flushes.clone();
+
+        let options = BufferOptions {
+            max_count: 2,
+            flush_interval: Duration::from_secs(60),
+            ..BufferOptions::default()
+        };
+
+        let (writer, worker) = BufferedWriter::new(before(bodies.clone()), options, move |flush| {
+            assert!(flush.result.is_ok());
+            recorded.lock().unwrap().push((flush.count, flush.bytes));
+        }).unwrap();
+
+        for i in 0..3 {
+            writer.clone().write(&measurement(i)).unwrap();
+        }
+
+        drop(writer);
+        Runtime::new().unwrap().block_on(worker).unwrap();
+
+        assert_eq!(vec!["key i=0i\nkey i=1i", "key i=2i"], *bodies.lock().unwrap());
+        assert_eq!(vec![(2, 16), (1, 8)], *flushes.lock().unwrap());
+    }
+
+    #[test]
+    fn test_flush_by_bytes() {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+
+        let options = BufferOptions {
+            max_bytes: 10,
+            flush_interval: Duration::from_secs(60),
+            ..BufferOptions::default()
+        };
+
+        let (writer, worker) = BufferedWriter::new(before(bodies.clone()), options, |_| {}).unwrap();
+
+        writer.write_line("key i=10i".to_string()).unwrap();
+        writer.write_line("key i=11i".to_string()).unwrap();
+        writer.write_line("key i=12i".to_string()).unwrap();
+
+        drop(writer);
+        Runtime::new().unwrap().block_on(worker).unwrap();
+
+        assert_eq!(vec!["key i=10i\nkey i=11i", "key i=12i"], *bodies.lock().unwrap());
+    }
+
+    #[test]
+    fn test_flush_by_interval() {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+
+        let options = BufferOptions {
+            flush_interval: Duration::from_millis(10),
+            ..BufferOptions::default()
+        };
+
+        let (writer, worker) = BufferedWriter::new(before(bodies.clone()), options, |_| {}).unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        rt.spawn(worker);
+        writer.write(&measurement(1)).unwrap();
+        rt.block_on(Delay::new(Instant::now() + Duration::from_millis(100))).unwrap();
+
+        assert_eq!(vec!["key i=1i"], *bodies.lock().unwrap());
+
+        writer.write(&measurement(2)).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        rt.run().unwrap();
+
+        assert_eq!(vec!["key i=1i", "key i=2i"], *bodies.lock().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let options = BufferOptions {
+            flush_interval: Duration::from_secs(0),
+            ..BufferOptions::default()
+        };
+
+        match BufferedWriter::new(before(Arc::new(Mutex::new(Vec::new()))), options, |_| {}) {
+            Err(ClientError::InvalidOptions(_)) => {},
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("options should be rejected")
+        }
+    }
+}
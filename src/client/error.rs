@@ -0,0 +1,567 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io;
+use serde_json;
+use ::hurl::HurlError;
+use ::measurement::Value;
+use ::parser::Parser;
+use ::parser::line::LineParser;
+
+/// Header with the error message, set by InfluxDB on failed requests.
+const ERROR_HEADER: &str = "x-influxdb-error";
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// Server responded to the write with 200 status, meaning that it was not completed.
+    CouldNotComplete(ServerError),
+    /// Request could not be sent or response could not be received.
+    Communication(HurlError),
+    /// Server rejected the request with 400 status.
+    Syntax(ServerError),
+    /// Server rejected some of the written points, while the others were written.
+    PartialWrite(PartialWrite),
+    /// Some batches of the write failed with the error, while the others were partially written.
+    PartialFailure(Box<ClientError>, PartialWrite),
+    /// Server responded with any other unexpected status.
+    Unexpected(ServerError),
+    /// Response could not be parsed.
+    Parse(String),
+    InvalidOptions(String),
+    Closed,
+    /// Write failed and was stored in the spool to be replayed later.
+    Spooled(Box<ClientError>),
+    /// Spool could not be read or written.
+    Spool(io::Error),
+    Timeout(HurlError)
+}
+
+impl ClientError {
+    /// Returns error response of the server, if request failed due to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::client::{ClientError, ServerError, ServerErrorKind};
+    /// use std::collections::HashMap;
+    ///
+    /// let e = ClientError::Unexpected(ServerError::new(404, &HashMap::new(), r#"{"error":"database not found: \"mydb\""}"#));
+    ///
+    /// let server = e.server_error().unwrap();
+    /// assert_eq!(404, server.status);
+    /// assert_eq!(ServerErrorKind::DatabaseNotFound, server.kind);
+    /// assert_eq!("database not found: \"mydb\"", server.message);
+    /// ```
+    pub fn server_error(&self) -> Option<&ServerError> {
+        match *self {
+            ClientError::CouldNotComplete(ref e) |
+            ClientError::Syntax(ref e) |
+            ClientError::Unexpected(ref e) => Some(e),
+            ClientError::PartialWrite(ref p) => p.errors.first(),
+            ClientError::PartialFailure(ref e, _) |
+            ClientError::Spooled(ref e) => e.server_error(),
+            _ => None
+        }
+    }
+
+    /// Returns points rejected by the server, if some batches of the write were partially written.
+    pub fn partial_write(&self) -> Option<&PartialWrite> {
+        match *self {
+            ClientError::PartialWrite(ref p) |
+            ClientError::PartialFailure(_, ref p) => Some(p),
+            ClientError::Spooled(ref e) => e.partial_write(),
+            _ => None
+        }
+    }
+
+    /// Returns status of the server response, if request failed due to it.
+    pub fn status(&self) -> Option<u16> {
+        self.server_error().map(|e| e.status)
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClientError::CouldNotComplete(ref e) => write!(f, "could not complete write: {}", e),
+            ClientError::Communication(ref e) => e.fmt(f),
+            ClientError::Syntax(ref e) => write!(f, "bad request: {}", e),
+            ClientError::Unexpected(ref e) => write!(f, "unexpected response: {}", e),
+            ClientError::PartialWrite(ref p) => p.fmt(f),
+            ClientError::PartialFailure(ref e, ref p) => write!(f, "{}; {}", e, p),
+            ClientError::Parse(ref reason) => f.write_str(reason),
+            ClientError::InvalidOptions(ref reason) => write!(f, "invalid options: {}", reason),
+            ClientError::Closed => f.write_str("client is closed"),
+            ClientError::Spooled(ref e) => write!(f, "write was spooled: {}", e),
+            ClientError::Spool(ref e) => write!(f, "spool error: {}", e),
+            ClientError::Timeout(ref e) => e.fmt(f)
+        }
+    }
+}
+
+impl error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ClientError::CouldNotComplete(ref e) |
+            ClientError::Syntax(ref e) |
+            ClientError::Unexpected(ref e) => Some(e),
+            ClientError::PartialWrite(ref p) => p.errors.first().map(|e| e as &(dyn error::Error + 'static)),
+            ClientError::PartialFailure(ref e, _) |
+            ClientError::Spooled(ref e) => Some(&**e),
+            ClientError::Communication(ref e) |
+            ClientError::Timeout(ref e) => Some(e),
+            ClientError::Spool(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// Kind of the server error, recognized by its message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServerErrorKind {
+    /// Field value has different type than the one already written to the shard.
+    FieldTypeConflict,
+    DatabaseNotFound,
+    /// Credentials are missing or invalid.
+    AuthFailed,
+    /// Points are older than the retention policy duration.
+    BeyondRetention,
+    /// Some of the points were written, while the others were dropped.
+    PartialWrite,
+    Other
+}
+
+impl fmt::Display for ServerErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            ServerErrorKind::FieldTypeConflict => "field type conflict",
+            ServerErrorKind::DatabaseNotFound  => "database not found",
+            ServerErrorKind::AuthFailed        => "authorization failed",
+            ServerErrorKind::BeyondRetention   => "points beyond retention policy",
+            ServerErrorKind::PartialWrite      => "partial write",
+            ServerErrorKind::Other             => "server error"
+        };
+
+        f.write_str(s)
+    }
+}
+
+/// Error response of the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerError {
+    pub status: u16,
+    pub kind: ServerErrorKind,
+
+    /// Error message, taken from the `X-Influxdb-Error` header or the `error` field of the JSON
+    /// body. Whole body is used when neither is present.
+    pub message: String,
+
+    /// Raw body of the response.
+    pub body: String
+}
+
+/// Error body of InfluxDB 1.x, with the `error` field, or of InfluxDB 2.x, with the `message` field.
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: Option<String>,
+    message: Option<String>
+}
+
+impl ServerError {
+    /// Constructs `ServerError` from the response, with lowercase header names.
+    pub fn new(status: u16, headers: &HashMap<String, String>, body: &str) -> ServerError {
+        let message = match headers.get(ERROR_HEADER) {
+            Some(message) => message.clone(),
+            None => serde_json::from_str::<ErrorBody>(body).ok()
+                .and_then(|parsed| parsed.error.or(parsed.message))
+                .unwrap_or_else(|| body.trim().to_string())
+        };
+
+        ServerError::with_message(status, message, body)
+    }
+
+    /// Constructs `ServerError` with the message, which is already extracted from the response.
+    pub fn with_message(status: u16, message: String, body: &str) -> ServerError {
+        ServerError {
+            status,
+            kind: kind(status, &message),
+            message,
+            body: body.to_string()
+        }
+    }
+}
+
+fn kind(status: u16, message: &str) -> ServerErrorKind {
+    if status == 401 || status == 403 || message.starts_with("authorization failed") || message.contains("unable to parse authentication credentials") {
+        ServerErrorKind::AuthFailed
+    } else if message.contains("field type conflict") {
+        ServerErrorKind::FieldTypeConflict
+    } else if message.contains("points beyond retention policy") {
+        ServerErrorKind::BeyondRetention
+    } else if message.starts_with("partial write") {
+        ServerErrorKind::PartialWrite
+    } else if message.starts_with("database not found") || (message.starts_with("bucket \"") && message.ends_with("not found")) {
+        ServerErrorKind::DatabaseNotFound
+    } else {
+        ServerErrorKind::Other
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "server responded with {}: {}", self.status, self.message)
+    }
+}
+
+impl error::Error for ServerError {}
+
+/// Points rejected by the server, while the other points of the write were written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialWrite {
+    /// Number of points dropped by the server. Server reports `dropped=0` for the lines it could
+    /// not parse, so it is at least the number of the identified rejected lines.
+    pub dropped: usize,
+
+    /// Rejected lines, which could be identified, ordered by their index.
+    pub rejected: Vec<RejectedLine>,
+
+    /// Server responses to the partially written batches.
+    pub errors: Vec<ServerError>
+}
+
+/// Line rejected by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedLine {
+    /// Index of the line, or of the measurement, in the written input.
+    pub index: usize,
+    pub reason: String
+}
+
+impl PartialWrite {
+    /// Parses partial write response to the batch of `lines`, which starts at `offset` of the input.
+    ///
+    /// Server writes the other points only when the message starts with `partial write`, so the error
+    /// is returned back otherwise. Rejected lines are identified by the `unable to parse '<line>'` and
+    /// `line <n>:` errors, and by the measurement, field and type of the field type conflicts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::client::{PartialWrite, ServerError};
+    /// use std::collections::HashMap;
+    ///
+    /// let lines = vec!["cpu value=1".to_string(), "cpu value=".to_string()];
+    /// let e = ServerError::new(400, &HashMap::new(), r#"{"error":"partial write: unable to parse 'cpu value=': missing field value dropped=0"}"#);
+    ///
+    /// let partial = PartialWrite::parse(e, &lines, 100).unwrap();
+    ///
+    /// assert_eq!(1, partial.dropped);
+    /// assert_eq!(101, partial.rejected[0].index);
+    /// assert_eq!("missing field value", partial.rejected[0].reason);
+    /// ```
+    pub fn parse(error: ServerError, lines: &[String], offset: usize) -> Result<PartialWrite, ServerError> {
+        if !error.message.starts_with("partial write") {
+            return Err(error);
+        }
+
+        let mut rejected: Vec<RejectedLine> = Vec::new();
+
+        for segment in error.message.split('\n') {
+            let is_rejected = |i: usize| rejected.iter().any(|r| r.index == offset + i);
+
+            // the same line could be written several times, so each error takes the next one
+            let found = match unparsed(segment) {
+                Some((line, reason)) => (0..lines.len())
+                    .find(|&i| lines[i] == line && !is_rejected(i))
+                    .map(|i| vec![(i, reason)])
+                    .unwrap_or_default(),
+                None => numbered(segment, lines.len())
+                    .map(|found| vec![found])
+                    .unwrap_or_else(|| conflicting(segment, lines))
+            };
+
+            for (i, reason) in found {
+                if !rejected.iter().any(|r| r.index == offset + i) {
+                    rejected.push(RejectedLine { index: offset + i, reason });
+                }
+            }
+        }
+
+        rejected.sort_by_key(|r| r.index);
+
+        Ok(PartialWrite {
+            dropped: dropped(&error.message).unwrap_or(0).max(rejected.len()),
+            rejected,
+            errors: vec![error]
+        })
+    }
+
+    /// Merges partial write of the other batch into this one.
+    pub fn merge(&mut self, other: PartialWrite) {
+        self.dropped += other.dropped;
+        self.rejected.extend(other.rejected);
+        self.rejected.sort_by_key(|r| r.index);
+        self.errors.extend(other.errors);
+    }
+}
+
+impl fmt::Display for PartialWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "partial write: {} points dropped", self.dropped)?;
+
+        if let Some(e) = self.errors.first() {
+            write!(f, ": {}", e.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `unable to parse '<line>': <reason>` error, returning the line and the reason.
+fn unparsed(segment: &str) -> Option<(&str, String)> {
+    const PREFIX: &str = "unable to parse '";
+
+    let start = segment.find(PREFIX)? + PREFIX.len();
+    let end = start + segment[start..].rfind("': ")?;
+
+    Some((&segment[start..end], reason(&segment[end + 3..])))
+}
+
+/// Parses `line <n>: <reason>` error, with line numbers starting from 1.
+fn numbered(segment: &str, len: usize) -> Option<(usize, String)> {
+    segment.match_indices("line ").find_map(|(i, prefix)| {
+        let rest = &segment[i + prefix.len()..];
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+
+        if digits == 0 || !rest[digits..].starts_with(": ") {
+            return None;
+        }
+
+        match rest[..digits].parse::<usize>() {
+            Ok(n) if n >= 1 && n <= len => Some((n - 1, reason(&rest[digits + 2..]))),
+            _ => None
+        }
+    })
+}
+
+/// Parses `input field "<field>" on measurement "<key>" is type <type>` error, returning indices of
+/// all lines in the batch which write the field of that type to the measurement.
+fn conflicting(segment: &str, lines: &[String]) -> Vec<(usize, String)> {
+    let (field, key, kind) = match conflict(segment) {
+        Some(conflict) => conflict,
+        None => return Vec::new()
+    };
+
+    let reason = match segment.find("field type conflict") {
+        Some(start) => reason(&segment[start..]),
+        None => reason(segment)
+    };
+
+    let parser = LineParser::new();
+
+    lines.iter().enumerate().filter_map(|(i, line)| {
+        let measurements = parser.parse(line).ok()?;
+
+        let matches = measurements.iter().any(|m| {
+            m.key == key && m.fields.get(field).is_some_and(|value| type_name(value) == kind)
+        });
+
+        if matches { Some((i, reason.clone())) } else { None }
+    }).collect()
+}
+
+fn conflict(segment: &str) -> Option<(&str, &str, &str)> {
+    let rest = &segment[segment.find("input field \"")? + 13..];
+    let field = &rest[..rest.find('"')?];
+
+    let rest = &rest[field.len()..];
+    let rest = &rest[rest.find("on measurement \"")? + 16..];
+    let key = &rest[..rest.find('"')?];
+
+    let rest = &rest[key.len()..];
+    let rest = &rest[rest.find("is type ")? + 8..];
+    let kind = rest.split(|c: char| !c.is_ascii_alphabetic()).next()?;
+
+    Some((field, key, kind))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::String(_) => "string",
+        Value::Float(_) => "float",
+        Value::Integer(_) => "integer",
+        Value::UInteger(_) => "unsigned",
+        Value::Boolean(_) => "boolean"
+    }
+}
+
+/// Returns reason of the error, without the `dropped=<n>` suffix of the last one.
+fn reason(s: &str) -> String {
+    s.split(" dropped=").next().unwrap_or_default().to_string()
+}
+
+fn dropped(message: &str) -> Option<usize> {
+    message.match_indices("dropped=").map(|(i, prefix)| {
+        let rest = &message[i + prefix.len()..];
+        let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        rest[..end].parse::<usize>().unwrap_or(0)
+    }).fold(None, |sum, n| Some(sum.unwrap_or(0) + n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientError, ServerError, ServerErrorKind, PartialWrite, RejectedLine};
+    use ::hurl::{HurlError, ErrorKind};
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::io;
+
+    fn server_error(status: u16, body: &str) -> ServerError {
+        ServerError::new(status, &HashMap::new(), body)
+    }
+
+    #[test]
+    fn test_message_from_json() {
+        let e = server_error(400, "{\"error\":\"unable to parse 'key value=': missing field value\"}\n");
+
+        assert_eq!("unable to parse 'key value=': missing field value", e.message);
+        assert_eq!(ServerErrorKind::Other, e.kind);
+    }
+
+    #[test]
+    fn test_message_from_header() {
+        let mut headers = HashMap::new();
+        headers.insert("x-influxdb-error".to_string(), "database not found: \"mydb\"".to_string());
+
+        let e = ServerError::new(404, &headers, "");
+
+        assert_eq!("database not found: \"mydb\"", e.message);
+        assert_eq!(ServerErrorKind::DatabaseNotFound, e.kind);
+    }
+
+    #[test]
+    fn test_message_from_plain_body() {
+        let e = server_error(502, "  Bad Gateway\n");
+
+        assert_eq!("Bad Gateway", e.message);
+        assert_eq!("  Bad Gateway\n", e.body);
+    }
+
+    #[test]
+    fn test_kinds() {
+        let cases = vec![
+            (400, r#"{"error":"partial write: field type conflict: input field \"value\" on measurement \"cpu\" is type float, already exists as type integer dropped=1"}"#, ServerErrorKind::FieldTypeConflict),
+            (400, r#"{"error":"partial write: points beyond retention policy dropped=2"}"#, ServerErrorKind::BeyondRetention),
+            (400, r#"{"error":"partial write: max-values-per-tag limit exceeded (100453/100000) dropped=1"}"#, ServerErrorKind::PartialWrite),
+            (401, r#"{"error":"authorization failed"}"#, ServerErrorKind::AuthFailed),
+            (403, r#"{"error":"\"gobwas\" user is not authorized to write to database \"mydb\""}"#, ServerErrorKind::AuthFailed),
+            (404, r#"{"error":"database not found: \"mydb\""}"#, ServerErrorKind::DatabaseNotFound),
+            (404, r#"{"code":"not found","message":"bucket \"mybucket\" not found"}"#, ServerErrorKind::DatabaseNotFound),
+            (500, r#"{"error":"timeout"}"#, ServerErrorKind::Other)
+        ];
+
+        for (status, body, kind) in cases {
+            assert_eq!(kind, server_error(status, body).kind, "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let e = ClientError::Syntax(server_error(400, r#"{"error":"bad timestamp"}"#));
+        assert_eq!("bad request: server responded with 400: bad timestamp", e.to_string());
+
+        let e = ClientError::Spooled(Box::new(ClientError::Unexpected(server_error(503, "unavailable"))));
+        assert_eq!("write was spooled: unexpected response: server responded with 503: unavailable", e.to_string());
+        assert_eq!(Some(503), e.status());
+    }
+
+    #[test]
+    fn test_source() {
+        let e = ClientError::Communication(HurlError::with_source(ErrorKind::Connect, "could not send request", io::Error::new(io::ErrorKind::ConnectionRefused, "refused")));
+        assert_eq!("connection error: could not send request: refused", e.to_string());
+        assert!(e.source().and_then(|e| e.downcast_ref::<HurlError>()).is_some_and(|e| e.kind() == ErrorKind::Connect));
+
+        let e = ClientError::Spool(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        assert_eq!("spool error: denied", e.to_string());
+        assert!(e.source().and_then(|e| e.downcast_ref::<io::Error>()).is_some());
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_partial_write_unparsed() {
+        let batch = lines(&["cpu value=1", "cpu value=", "cpu value=3", "mem"]);
+        let e = server_error(400, r#"{"error":"partial write: unable to parse 'cpu value=': missing field value\nunable to parse 'mem': missing fields dropped=0"}"#);
+
+        let partial = PartialWrite::parse(e, &batch, 10).unwrap();
+
+        assert_eq!(2, partial.dropped);
+        assert_eq!(vec![
+            RejectedLine { index: 11, reason: "missing field value".to_string() },
+            RejectedLine { index: 13, reason: "missing fields".to_string() }
+        ], partial.rejected);
+    }
+
+    #[test]
+    fn test_partial_write_repeated_lines() {
+        let batch = lines(&["cpu value=", "cpu value=1", "cpu value="]);
+        let e = server_error(400, r#"{"error":"partial write: unable to parse 'cpu value=': missing field value\nunable to parse 'cpu value=': missing field value dropped=0"}"#);
+
+        let partial = PartialWrite::parse(e, &batch, 0).unwrap();
+
+        assert_eq!(2, partial.dropped);
+        assert_eq!(vec![0, 2], partial.rejected.iter().map(|r| r.index).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_partial_write_numbered() {
+        let batch = lines(&["cpu value=1", "cpu value=", "cpu value=3"]);
+        let e = server_error(400, r#"{"error":"partial write: errors encountered on line(s): line 2: no field value found"}"#);
+
+        let partial = PartialWrite::parse(e, &batch, 0).unwrap();
+
+        assert_eq!(vec![RejectedLine { index: 1, reason: "no field value found".to_string() }], partial.rejected);
+    }
+
+    #[test]
+    fn test_partial_write_field_type_conflict() {
+        let batch = lines(&["cpu value=1i", "cpu value=1.5", "mem value=2.5", "cpu,host=a value=2.5 10"]);
+        let e = server_error(400, r#"{"error":"partial write: field type conflict: input field \"value\" on measurement \"cpu\" is type float, already exists as type integer dropped=2"}"#);
+
+        let partial = PartialWrite::parse(e, &batch, 0).unwrap();
+
+        assert_eq!(2, partial.dropped);
+        assert_eq!(vec![1, 3], partial.rejected.iter().map(|r| r.index).collect::<Vec<_>>());
+        assert_eq!("field type conflict: input field \"value\" on measurement \"cpu\" is type float, already exists as type integer", partial.rejected[0].reason);
+    }
+
+    #[test]
+    fn test_partial_write_beyond_retention() {
+        let e = server_error(400, r#"{"error":"partial write: points beyond retention policy dropped=3"}"#);
+
+        let partial = PartialWrite::parse(e, &lines(&["cpu value=1 0"]), 0).unwrap();
+
+        assert_eq!(3, partial.dropped);
+        assert!(partial.rejected.is_empty());
+    }
+
+    #[test]
+    fn test_not_partial_write() {
+        let batch = lines(&["cpu value=", "mem"]);
+        let e = server_error(400, r#"{"error":"unable to parse 'cpu value=': missing field value\nunable to parse 'mem': missing fields"}"#);
+
+        assert_eq!(e.clone(), PartialWrite::parse(e, &batch, 0).unwrap_err());
+    }
+
+    #[test]
+    fn test_partial_write_merge() {
+        let mut partial = PartialWrite::parse(server_error(400, r#"{"error":"partial write: unable to parse 'b': missing fields dropped=0"}"#), &lines(&["a x=1", "b"]), 2).unwrap();
+        partial.merge(PartialWrite::parse(server_error(400, r#"{"error":"partial write: unable to parse 'a=': missing fields dropped=0"}"#), &lines(&["a=", "b x=1"]), 0).unwrap());
+
+        assert_eq!(2, partial.dropped);
+        assert_eq!(vec![0, 3], partial.rejected.iter().map(|r| r.index).collect::<Vec<_>>());
+        assert_eq!(2, partial.errors.len());
+    }
+}
@@ -0,0 +1,266 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use rand::{thread_rng, Rng};
+
+/// Strategy of spreading requests across the hosts.
+///
+/// Whatever strategy is used, unhealthy hosts are tried only after all healthy ones.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Balance {
+    /// Hosts are tried in order they were added, so the first healthy host gets all requests.
+    #[default]
+    Ordered,
+    /// Each request starts from the next host.
+    RoundRobin,
+    /// Each request starts from the random host.
+    Random,
+    /// Each request starts from the host with the least number of requests in flight.
+    LeastInFlight
+}
+
+/// List of InfluxDB hosts with their health state.
+///
+/// Host is marked unhealthy when request to it fails, and becomes healthy again after cooldown
+/// or after successful request.
+#[derive(Default)]
+pub struct Hosts {
+    hosts: Mutex<Vec<Host>>,
+    next: AtomicUsize
+}
+
+struct Host {
+    url: String,
+    unhealthy_until: Option<Instant>,
+    in_flight: usize
+}
+
+impl Host {
+    fn is_healthy(&self, now: Instant) -> bool {
+        match self.unhealthy_until {
+            Some(until) => until <= now,
+            None => true
+        }
+    }
+}
+
+impl Hosts {
+    /// Constructs new empty `Hosts`.
+    pub fn new() -> Hosts {
+        Hosts::default()
+    }
+
+    /// Adds host with given url.
+    pub fn add(&self, url: &str) {
+        self.hosts.lock().unwrap().push(Host {
+            url: url.to_string(),
+            unhealthy_until: None,
+            in_flight: 0
+        });
+    }
+
+    /// Returns number of hosts.
+    pub fn len(&self) -> usize {
+        self.hosts.lock().unwrap().len()
+    }
+
+    /// Returns `true` if there are no hosts.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether host with given index is healthy.
+    pub fn is_healthy(&self, index: usize) -> bool {
+        self.hosts.lock().unwrap()[index].is_healthy(Instant::now())
+    }
+
+    /// Returns number of requests in flight to the host with given index.
+    pub fn in_flight(&self, index: usize) -> usize {
+        self.hosts.lock().unwrap()[index].in_flight
+    }
+
+    /// Returns indexes and urls of hosts in order they should be tried.
+    ///
+    /// Healthy hosts go first, in order defined by `balance`. Unhealthy hosts go last, ordered by the
+    /// end of their cooldown, so they are still tried when no healthy host left.
+    pub fn order(&self, balance: Balance) -> Vec<(usize, String)> {
+        let hosts = self.hosts.lock().unwrap();
+        let now = Instant::now();
+
+        let mut healthy = Vec::new();
+        let mut unhealthy = Vec::new();
+
+        for (i, host) in hosts.iter().enumerate() {
+            if host.is_healthy(now) {
+                healthy.push((i, host.url.clone()));
+            } else {
+                unhealthy.push((host.unhealthy_until, (i, host.url.clone())));
+            }
+        }
+
+        if !healthy.is_empty() {
+            match balance {
+                Balance::Ordered => {},
+                Balance::RoundRobin => {
+                    let n = self.next.fetch_add(1, Ordering::Relaxed) % healthy.len();
+                    healthy.rotate_left(n);
+                },
+                Balance::Random => {
+                    let n = thread_rng().gen_range(0, healthy.len());
+                    healthy.rotate_left(n);
+                },
+                Balance::LeastInFlight => {
+                    // rotate first, so hosts with equal load take turns
+                    let n = self.next.fetch_add(1, Ordering::Relaxed) % healthy.len();
+                    healthy.rotate_left(n);
+                    healthy.sort_by_key(|&(i, _)| hosts[i].in_flight);
+                }
+            }
+        }
+
+        unhealthy.sort_by_key(|&(until, _)| until);
+        healthy.extend(unhealthy.into_iter().map(|(_, host)| host));
+
+        healthy
+    }
+
+    /// Marks host as unhealthy for the `cooldown` duration.
+    pub fn mark_unhealthy(&self, index: usize, cooldown: Duration) {
+        self.hosts.lock().unwrap()[index].unhealthy_until = Some(Instant::now() + cooldown);
+    }
+
+    /// Marks host as healthy.
+    pub fn mark_healthy(&self, index: usize) {
+        self.hosts.lock().unwrap()[index].unhealthy_until = None;
+    }
+}
+
+/// Guard of the request in flight to the host.
+///
+/// Host's number of requests in flight is increased on construction and decreased on drop.
+pub struct InFlight {
+    hosts: Arc<Hosts>,
+    index: usize
+}
+
+impl InFlight {
+    /// Starts request to the host with given index.
+    pub fn new(hosts: Arc<Hosts>, index: usize) -> InFlight {
+        hosts.hosts.lock().unwrap()[index].in_flight += 1;
+
+        InFlight {
+            hosts,
+            index
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.hosts.hosts.lock().unwrap()[self.index].in_flight -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Balance, Hosts, InFlight};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn urls(hosts: &Hosts) -> Vec<String> {
+        balanced(hosts, Balance::Ordered)
+    }
+
+    fn balanced(hosts: &Hosts, balance: Balance) -> Vec<String> {
+        hosts.order(balance).into_iter().map(|(_, url)| url).collect()
+    }
+
+    #[test]
+    fn test_order() {
+        let hosts = Hosts::new();
+        hosts.add("a");
+        hosts.add("b");
+        hosts.add("c");
+
+        assert_eq!(vec!["a", "b", "c"], urls(&hosts));
+
+        hosts.mark_unhealthy(0, Duration::from_secs(20));
+        hosts.mark_unhealthy(1, Duration::from_secs(10));
+
+        assert!(!hosts.is_healthy(0));
+        assert_eq!(vec!["c", "b", "a"], urls(&hosts));
+
+        hosts.mark_healthy(0);
+
+        assert!(hosts.is_healthy(0));
+        assert_eq!(vec!["a", "c", "b"], urls(&hosts));
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let hosts = Hosts::new();
+        hosts.add("a");
+        hosts.add("b");
+
+        hosts.mark_unhealthy(0, Duration::from_secs(0));
+
+        assert!(hosts.is_healthy(0));
+        assert_eq!(vec!["a", "b"], urls(&hosts));
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let hosts = Hosts::new();
+        hosts.add("a");
+        hosts.add("b");
+        hosts.add("c");
+
+        assert_eq!(vec!["a", "b", "c"], balanced(&hosts, Balance::RoundRobin));
+        assert_eq!(vec!["b", "c", "a"], balanced(&hosts, Balance::RoundRobin));
+
+        hosts.mark_unhealthy(0, Duration::from_secs(10));
+
+        assert_eq!(vec!["b", "c", "a"], balanced(&hosts, Balance::RoundRobin));
+        assert_eq!(vec!["c", "b", "a"], balanced(&hosts, Balance::RoundRobin));
+    }
+
+    #[test]
+    fn test_random() {
+        let hosts = Hosts::new();
+        hosts.add("a");
+        hosts.add("b");
+        hosts.add("c");
+
+        hosts.mark_unhealthy(2, Duration::from_secs(10));
+
+        for _ in 0..10 {
+            let mut urls = balanced(&hosts, Balance::Random);
+
+            assert_eq!("c", urls.pop().unwrap());
+            urls.sort();
+            assert_eq!(vec!["a", "b"], urls);
+        }
+    }
+
+    #[test]
+    fn test_least_in_flight() {
+        let hosts = Arc::new(Hosts::new());
+        hosts.add("a");
+        hosts.add("b");
+        hosts.add("c");
+
+        let a = InFlight::new(hosts.clone(), 0);
+        let _b = InFlight::new(hosts.clone(), 1);
+        let _c = InFlight::new(hosts.clone(), 2);
+        let _c = InFlight::new(hosts.clone(), 2);
+
+        assert_eq!(2, hosts.in_flight(2));
+        assert_eq!(vec!["a", "b", "c"], balanced(&hosts, Balance::LeastInFlight));
+        assert_eq!(vec!["b", "a", "c"], balanced(&hosts, Balance::LeastInFlight));
+
+        drop(a);
+
+        assert_eq!(0, hosts.in_flight(0));
+        assert_eq!(vec!["a", "b", "c"], balanced(&hosts, Balance::LeastInFlight));
+    }
+}
@@ -1,149 +1,866 @@
 use ::measurement::Measurement;
 use ::serializer::Serializer;
-use ::client::{Precision, Client, Credentials, ClientError, ClientReadResult, ClientWriteResult};
-use ::hurl::{Hurl, Request, Method, Auth};
+use ::client::{Client, Credentials, TokenCredentials, WriteOptions, QueryOptions, QueryFormat, FluxOptions, FluxBody, ClientError, ServerError, PartialWrite, ClientFluxResult, ClientReadResult, ClientQueryResult, ClientQueryStream, ClientWriteResult, ClientReplayResult};
+use ::query::QueryResponse;
+use ::query::csv::{self, Table};
+use ::client::hosts::{Balance, Hosts, InFlight};
+use ::client::retry::RetryPolicy;
+use ::client::spool::Spool;
+use ::hurl::{Hurl, Request, Response, StreamResponse, Method, Auth, HurlResult, HurlStreamResult, HurlError, ErrorKind};
+use ::hurl::lines::Lines;
 use std::collections::HashMap;
-use futures::{Future, stream, Stream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures::{future, Async, Future, stream, Stream};
+use futures::future::{Either, Loop};
+use tokio::timer::{Delay, Timeout};
+use serde_json;
 
 const MAX_BATCH: u16 = 5000;
 
+const HOST_COOLDOWN: u64 = 30;
+
 pub enum WriteStatus {
     Success,
     CouldNotComplete,
 }
 
-// fixme
-pub struct Options {
-    pub max_batch: Option<u16>,
-    pub precision: Option<Precision>,
+/// InfluxDB HTTP API client.
+///
+/// Client owns its configuration, so futures it returns do not borrow it. Clones of the client share
+/// the serializer, transport, hosts and spool.
+///
+/// Client constructed `with_token` writes with the InfluxDB 2.x API, and queries with its InfluxQL
+/// compatibility endpoint, using the bucket as the database.
+#[derive(Clone)]
+pub struct HttpClient {
+    api: Arc<Api>,
+    serializer: Arc<dyn Serializer + Send + Sync>,
+    hurl: Arc<dyn Hurl + Send + Sync>,
+    hosts: Arc<Hosts>,
+    spool: Option<Arc<Spool>>,
 
-    pub epoch: Option<Precision>,
-    pub chunk_size: Option<u16>
-}
+    /// Duration for which host is not used after failed request, unless all other hosts failed too.
+    pub host_cooldown: Duration,
+
+    /// Strategy of spreading requests across the hosts.
+    pub balance: Balance,
+
+    /// Policy of retrying failed write requests.
+    pub retry_policy: RetryPolicy,
 
-pub struct HttpClient<'a> {
-    credentials: Credentials<'a>,
-    serializer: Box<Serializer + Send + Sync>,
-    hurl: Box<Hurl + Send + Sync>,
-    hosts: Vec<&'a str>,
-    pub max_batch: u16
+    /// Default options of the write requests.
+    pub write_options: WriteOptions,
+
+    /// Default options of the query requests.
+    pub query_options: QueryOptions,
+
+    /// Default options of the Flux query requests.
+    pub flux_options: FluxOptions
 }
 
-impl<'a> HttpClient<'a> {
-    pub fn new(credentials: Credentials<'a>, serializer: Box<Serializer + Send + Sync>, hurl: Box<Hurl + Send + Sync>) -> HttpClient<'a> {
+impl HttpClient {
+    pub fn new(credentials: Credentials, serializer: Box<dyn Serializer + Send + Sync>, hurl: Box<dyn Hurl + Send + Sync>) -> HttpClient {
         HttpClient {
-            credentials: credentials,
-            serializer: serializer,
-            hurl: hurl,
-            hosts: vec![],
-            max_batch: MAX_BATCH
+            api: Arc::new(Api::V1 {
+                username: credentials.username.to_string(),
+                password: credentials.password.to_string(),
+                database: credentials.database.to_string()
+            }),
+            serializer: Arc::from(serializer),
+            hurl: Arc::from(hurl),
+            hosts: Arc::new(Hosts::new()),
+            spool: None,
+            host_cooldown: Duration::from_secs(HOST_COOLDOWN),
+            balance: Balance::default(),
+            retry_policy: RetryPolicy::default(),
+            write_options: WriteOptions::default(),
+            query_options: QueryOptions::default(),
+            flux_options: FluxOptions::default()
         }
     }
 
-    pub fn add_host(&mut self, host: &'a str) {
-        self.hosts.push(host);
+    /// Constructs client of the InfluxDB 2.x API.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::client::TokenCredentials;
+    /// use influent::client::http::HttpClient;
+    /// use influent::hurl::hyper::HyperHurl;
+    /// use influent::serializer::line::LineSerializer;
+    ///
+    /// let credentials = TokenCredentials {
+    ///     token: "xxx",
+    ///     org: "gobwas",
+    ///     bucket: "mybucket"
+    /// };
+    ///
+    /// let mut client = HttpClient::with_token(credentials, Box::new(LineSerializer::new()), Box::new(HyperHurl::new().unwrap()));
+    /// client.add_host("http://localhost:8086");
+    /// ```
+    pub fn with_token(credentials: TokenCredentials, serializer: Box<dyn Serializer + Send + Sync>, hurl: Box<dyn Hurl + Send + Sync>) -> HttpClient {
+        let mut client = HttpClient::new(Credentials { username: "", password: "", database: "" }, serializer, hurl);
+
+        client.api = Arc::new(Api::V2 {
+            token: credentials.token.to_string(),
+            org: credentials.org.to_string(),
+            bucket: credentials.bucket.to_string()
+        });
+
+        client
+    }
+
+    pub fn add_host(&mut self, host: &str) {
+        self.hosts.add(host);
+    }
+
+    /// Sets spool for the batches which could not be written.
+    ///
+    /// Batch is pushed to the spool when write fails at the transport level or with a server error,
+    /// even after retries. Such writes result in `ClientError::Spooled`. Spooled batches are sent
+    /// again by `replay`.
+    pub fn set_spool(&mut self, spool: Spool) {
+        self.spool = Some(Arc::new(spool));
+    }
+
+    /// Serializes measurement with the client's serializer.
+    pub fn serialize(&self, measurement: &Measurement) -> String {
+        self.serializer.serialize(measurement)
     }
 
-    fn get_host(&self) -> &'a str {
-        match self.hosts.first() {
-            Some(host) => host,
-            None => panic!("Could not get host")
+    /// Writes already serialized measurements.
+    ///
+    /// Lines are sent in batches of `max_batch` lines, as `write_many` does. Resolves after all batches
+    /// complete, failing with the error of the first failed batch. When server rejects only some of
+    /// the lines, fails with `ClientError::PartialWrite`, with the rejected lines indexed in `lines`,
+    /// or with `ClientError::PartialFailure`, if the other batches failed as well.
+    pub fn write_lines(&self, lines: &[String], options: Option<WriteOptions>) -> ClientWriteResult {
+        let options = options.unwrap_or_default().or(&self.write_options);
+
+        if let Err(e) = options.validate().and_then(|_| self.api.validate(&options)) {
+            return Box::new(future::err(e));
         }
+
+        let max_batch = options.max_batch.unwrap_or(MAX_BATCH);
+
+        let futures = lines.chunks(max_batch as usize).enumerate().map(|(i, chunk)| {
+            let offset = i * max_batch as usize;
+            let batch = chunk.to_vec();
+            let body = chunk.join("\n");
+            let query = self.api.write_query(&options);
+            let request = self.template(Method::POST, self.api.write_path(), query, Some(body.clone()), options.connect_timeout, options.request_timeout);
+
+            let spool = self.spool.clone();
+            let options = options.clone();
+
+            limit(self.send_retrying(request), options.total_timeout).then(move |res| {
+                let unavailable = match res {
+                    Ok(ref resp) => resp.status >= 500,
+                    Err(_) => true
+                };
+
+                match (write_result(res), spool) {
+                    (Err(e), Some(ref spool)) if unavailable => match spool.push(&body, &options) {
+                        Ok(()) => Err(ClientError::Spooled(Box::new(e))),
+                        Err(_) => Err(e)
+                    },
+                    (result, _) => result.map_err(|e| partial_write(e, &batch, offset))
+                }
+            }).then(Ok::<_, ClientError>)
+        });
+
+        // every batch is driven to completion, so that failed batches are spooled, and partial writes
+        // of all batches are reported together
+        let writes = stream::futures_ordered(futures).fold((None, None), |(partial, failed): (Option<PartialWrite>, Option<ClientError>), res| {
+            let outcome = match (res, partial, failed) {
+                (Ok(()), partial, failed) => (partial, failed),
+                (Err(ClientError::PartialWrite(p)), None, failed) => (Some(p), failed),
+                (Err(ClientError::PartialWrite(p)), Some(mut partial), failed) => {
+                    partial.merge(p);
+                    (Some(partial), failed)
+                },
+                (Err(e), partial, None) => (partial, Some(e)),
+                (Err(_), partial, failed) => (partial, failed)
+            };
+
+            Ok::<_, ClientError>(outcome)
+        });
+
+        Box::new(writes.and_then(|outcome| match outcome {
+            (Some(partial), Some(e)) => Err(ClientError::PartialFailure(Box::new(e), partial)),
+            (None, Some(e)) => Err(e),
+            (Some(partial), None) => Err(ClientError::PartialWrite(partial)),
+            (None, None) => Ok(())
+        }))
     }
-}
 
-impl<'a> Client for HttpClient<'a> {
-    fn query(&self, q: String, epoch: Option<Precision>) -> ClientReadResult {
-        let host = self.get_host();
+    /// Sends spooled batches in order they were spooled.
+    ///
+    /// Resolves with the number of written batches, when spool is empty. Batches rejected by the
+    /// server are dropped. Fails when batch could not be written due to unavailable server, leaving it
+    /// in the spool. Resolves immediately if spool is already being replayed.
+    pub fn replay(&self) -> ClientReplayResult {
+        let spool = match self.spool {
+            Some(ref spool) => spool.clone(),
+            None => return Box::new(future::ok(0))
+        };
+
+        if spool.is_empty() || !spool.start_replay() {
+            return Box::new(future::ok(0));
+        }
+
+        let sender = self.sender();
+        let policy = self.retry_policy.clone();
+        let client = self.clone();
+
+        let guard = ReplayGuard(spool.clone());
+
+        let replay = future::loop_fn(0, move |written| {
+            let batch = match spool.front() {
+                Ok(Some(batch)) => batch,
+                Ok(None) => return Either::A(future::ok(Loop::Break(written))),
+                Err(e) => return Either::A(future::err(ClientError::Spool(e)))
+            };
+
+            let options = batch.options.clone().or(&client.write_options);
+            let query = client.api.write_query(&options);
+            let request = client.template(Method::POST, client.api.write_path(), query, Some(batch.body.clone()), options.connect_timeout, options.request_timeout);
+
+            let spool = spool.clone();
+
+            Either::B(limit(sender.send_retrying(Arc::new(request), policy.clone()), options.total_timeout).then(move |res| {
+                match write_result(res) {
+                    Ok(()) => {
+                        spool.ack(&batch).map_err(ClientError::Spool)?;
+                        Ok(Loop::Continue(written + 1))
+                    },
+                    Err(ClientError::Syntax(_)) | Err(ClientError::CouldNotComplete(_)) => {
+                        spool.ack(&batch).map_err(ClientError::Spool)?;
+                        Ok(Loop::Continue(written))
+                    },
+                    Err(e) => Err(e)
+                }
+            }))
+        });
+
+        // replay flag is cleared also when the future is dropped before completion
+        Box::new(replay.then(move |res| {
+            drop(guard);
+            res
+        }))
+    }
+
+    /// Sends Flux query to the `/api/v2/query` endpoint, parsing annotated CSV response into tables.
+    ///
+    /// Fails with `ClientError::Unexpected`, if response reports an error of the query execution.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// extern crate futures;
+    /// extern crate influent;
+    ///
+    /// use futures::Future;
+    /// use influent::client::TokenCredentials;
+    /// use influent::client::http::HttpClient;
+    /// use influent::hurl::hyper::HyperHurl;
+    /// use influent::serializer::line::LineSerializer;
+    ///
+    /// # fn main() {
+    /// let credentials = TokenCredentials { token: "xxx", org: "gobwas", bucket: "mybucket" };
+    /// let mut client = HttpClient::with_token(credentials, Box::new(LineSerializer::new()), Box::new(HyperHurl::new().unwrap()));
+    /// client.add_host("http://localhost:8086");
+    ///
+    /// let script = "from(bucket: \"mybucket\") |> range(start: -1h)".to_string();
+    /// let tables = client.query_flux(script, None).wait().unwrap();
+    ///
+    /// for table in tables {
+    ///     println!("{:?}", table.records);
+    /// }
+    /// # }
+    /// ```
+    pub fn query_flux(&self, script: String, options: Option<FluxOptions>) -> ClientFluxResult {
+        let options = options.unwrap_or_default().or(&self.flux_options);
+
+        let mut query = HashMap::new();
+
+        if let Api::V2 { ref org, .. } = *self.api {
+            query.insert("org", org.clone());
+        }
+
+        let (content_type, body) = match options.body.unwrap_or(FluxBody::Json) {
+            FluxBody::Json => ("application/json", flux_request(&script)),
+            FluxBody::Flux => ("application/vnd.flux", script)
+        };
+
+        let mut request = self.template(Method::POST, "/api/v2/query", query, Some(body), options.connect_timeout, options.request_timeout);
+        request.headers.insert("Content-Type", content_type.to_string());
+        request.headers.insert("Accept", "application/csv".to_string());
+
+        Box::new(limit(self.send(request), options.total_timeout).then(|res| {
+            match res {
+                Ok(resp) => match resp.status {
+                    200 => flux_tables(resp),
+                    400 => Err(ClientError::Syntax(server_error(&resp))),
+                    _ => Err(ClientError::Unexpected(server_error(&resp)))
+                },
+                Err(reason) => Err(transport_error(reason))
+            }
+        }))
+    }
+
+    /// Sends query asking for MessagePack response, which is read as bytes.
+    fn query_msgpack(&self, q: String, options: QueryOptions) -> ClientQueryResult {
+        if let Err(e) = options.validate() {
+            return Box::new(future::err(e));
+        }
+
+        let query = self.query_params(q, &options);
+        let mut request = self.template(Method::GET, "/query", query, None, options.connect_timeout, options.request_timeout);
+        request.headers.insert("Accept", "application/x-msgpack".to_string());
+
+        let response = self.send_stream(request).and_then(|resp| {
+            let status = resp.status;
+            let headers = resp.headers;
+
+            resp.body.concat2().map(move |body| (status, headers, body))
+        });
+
+        Box::new(limit(Box::new(response), options.total_timeout).then(|res| {
+            match res {
+                Ok((200, _, body)) => QueryResponse::from_msgpack(&body).map_err(|e| ClientError::Parse(format!("Could not parse response: {}", e))),
+                Ok((status, headers, body)) => {
+                    let e = ServerError::new(status, &headers, &String::from_utf8_lossy(&body));
+
+                    match status {
+                        400 => Err(ClientError::Syntax(e)),
+                        _ => Err(ClientError::Unexpected(e))
+                    }
+                },
+                Err(reason) => Err(transport_error(reason))
+            }
+        }))
+    }
+
+    fn template(&self, method: Method, path: &'static str, query: HashMap<&'static str, String>, body: Option<String>, connect_timeout: Option<Duration>, timeout: Option<Duration>) -> Template {
+        Template {
+            method,
+            path,
+            api: self.api.clone(),
+            query,
+            headers: HashMap::new(),
+            body,
+            connect_timeout,
+            timeout
+        }
+    }
+
+    fn sender(&self) -> Sender {
+        Sender {
+            hurl: self.hurl.clone(),
+            hosts: self.hosts.clone(),
+            balance: self.balance,
+            cooldown: self.host_cooldown
+        }
+    }
+
+    fn send(&self, template: Template) -> HurlResult {
+        self.sender().send(Arc::new(template))
+    }
+
+    fn send_stream(&self, template: Template) -> HurlStreamResult {
+        self.sender().send_stream(Arc::new(template))
+    }
+
+    fn send_retrying(&self, template: Template) -> HurlResult {
+        self.sender().send_retrying(Arc::new(template), self.retry_policy.clone())
+    }
 
+    fn query_params(&self, q: String, options: &QueryOptions) -> HashMap<&'static str, String> {
         let mut query = HashMap::new();
-        query.insert("db", self.credentials.database.to_string());
+        query.insert("db", self.api.database().to_string());
         query.insert("q", q);
 
-        if let Some(ref epoch) = epoch {
+        if let Some(ref epoch) = options.epoch {
             query.insert("epoch", epoch.to_string());
         }
 
-        let request = Request {
-            url: &*{host.to_string() + "/query"},
-            method: Method::GET,
-            auth: Some(Auth {
-                username: self.credentials.username,
-                password: self.credentials.password
-            }),
-            query: Some(query),
-            body: None
-        };
+        if let Some(ref rp) = options.retention_policy {
+            query.insert("rp", rp.clone());
+        }
+
+        query
+    }
+}
+
+/// Marks spool as not being replayed, when dropped.
+struct ReplayGuard(Arc<Spool>);
+
+impl Drop for ReplayGuard {
+    fn drop(&mut self) {
+        self.0.stop_replay();
+    }
+}
+
+impl Client for HttpClient {
+    fn query(&self, q: String, options: Option<QueryOptions>) -> ClientReadResult {
+        let options = options.unwrap_or_default().or(&self.query_options);
+
+        if let Err(e) = options.validate() {
+            return Box::new(future::err(e));
+        }
 
-        Box::new(self.hurl.request(request).then(|res| {
+        let query = self.query_params(q, &options);
+        let mut request = self.template(Method::GET, "/query", query, None, options.connect_timeout, options.request_timeout);
+
+        match options.format {
+            Some(QueryFormat::Csv) => {
+                request.headers.insert("Accept", "application/csv".to_string());
+            },
+            Some(QueryFormat::Msgpack) => {
+                return Box::new(future::err(ClientError::InvalidOptions("msgpack response could not be read as string, use query_results".to_string())));
+            },
+            _ => {}
+        }
+
+        Box::new(limit(self.send(request), options.total_timeout).then(|res| {
             match res {
-                Ok(ref resp) if resp.status == 200 => Ok(resp.to_string()),
-                Ok(ref resp) if resp.status == 400 => Err(ClientError::Syntax(resp.to_string())),
-                Ok(ref resp) => Err(ClientError::Unexpected(format!("Unexpected response. Status: {}; Body: \"{}\"", resp.status, resp.to_string()))),
-                Err(reason) => Err(ClientError::Communication(reason))
+                Ok(resp) => match resp.status {
+                    200 => Ok(resp.body),
+                    400 => Err(ClientError::Syntax(server_error(&resp))),
+                    _ => Err(ClientError::Unexpected(server_error(&resp)))
+                },
+                Err(reason) => Err(transport_error(reason))
             }
         }))
     }
 
-    fn write_one(&self, measurement: Measurement, precision: Option<Precision>) -> ClientWriteResult {
-        self.write_many(&[measurement], precision)
+    fn query_results(&self, q: String, options: Option<QueryOptions>) -> ClientQueryResult {
+        let options = options.unwrap_or_default().or(&self.query_options);
+        let format = options.format;
+
+        if format == Some(QueryFormat::Msgpack) {
+            return self.query_msgpack(q, options);
+        }
+
+        Box::new(self.query(q, Some(options)).and_then(move |body| {
+            let response = match format {
+                Some(QueryFormat::Csv) => QueryResponse::from_csv(&body).map_err(|e| e.to_string()),
+                _ => QueryResponse::from_json(&body).map_err(|e| e.to_string())
+            };
+
+            response.map_err(|e| ClientError::Parse(format!("Could not parse response: {}; Body: \"{}\"", e, body)))
+        }))
     }
 
-    fn write_many(&self, measurements: &[Measurement], precision: Option<Precision>) -> ClientWriteResult {
-        let host = self.get_host();
+    fn query_chunked(&self, q: String, options: Option<QueryOptions>) -> ClientQueryStream {
+        let options = options.unwrap_or_default().or(&self.query_options);
+
+        if let Err(e) = options.validate() {
+            return Box::new(stream::once(Err(e)));
+        }
+
+        if options.format.is_some_and(|format| format != QueryFormat::Json) {
+            return Box::new(stream::once(Err(ClientError::InvalidOptions("chunked queries support only JSON format".to_string()))));
+        }
+
+        let mut query = self.query_params(q, &options);
+        query.insert("chunked", "true".to_string());
+
+        if let Some(chunk_size) = options.chunk_size {
+            query.insert("chunk_size", chunk_size.to_string());
+        }
+
+        let request = self.template(Method::GET, "/query", query, None, options.connect_timeout, options.request_timeout);
+
+        let chunks = self.send_stream(request).map_err(transport_error).and_then(|resp| {
+            let status = resp.status;
+            let headers = resp.headers;
+            let body = resp.body.map_err(transport_error);
 
-        let futures = measurements.chunks(self.max_batch as usize).map(|chunk| {
-            let mut lines = Vec::new();
+            if status == 200 {
+                let chunks = Lines::new(body)
+                    .filter(|line| !line.is_empty())
+                    .and_then(|line| {
+                        serde_json::from_slice(&line).map_err(|e| ClientError::Parse(format!("Could not parse chunk: {}; Chunk: \"{}\"", e, String::from_utf8_lossy(&line))))
+                    });
 
-            for measurement in chunk {
-                lines.push(self.serializer.serialize(measurement));
+                return Either::A(future::ok(Box::new(chunks) as ClientQueryStream));
             }
 
-            let mut query = HashMap::new();
-            query.insert("db", self.credentials.database.to_string());
+            Either::B(body.concat2().and_then(move |body| {
+                let e = ServerError::new(status, &headers, &String::from_utf8_lossy(&body));
+
+                match status {
+                    400 => Err(ClientError::Syntax(e)),
+                    _ => Err(ClientError::Unexpected(e))
+                }
+            }))
+        }).flatten_stream();
+
+        deadline(chunks, options.total_timeout)
+    }
+
+    fn write_one(&self, measurement: Measurement, options: Option<WriteOptions>) -> ClientWriteResult {
+        self.write_many(&[measurement], options)
+    }
+
+    fn write_many(&self, measurements: &[Measurement], options: Option<WriteOptions>) -> ClientWriteResult {
+        let lines: Vec<String> = measurements.iter().map(|m| self.serialize(m)).collect();
+
+        self.write_lines(&lines, options)
+    }
+}
+
+/// InfluxDB API used by the client, with its credentials.
+enum Api {
+    V1 {
+        username: String,
+        password: String,
+        database: String
+    },
+    V2 {
+        token: String,
+        org: String,
+        bucket: String
+    }
+}
+
+impl Api {
+    fn database(&self) -> &str {
+        match *self {
+            Api::V1 { ref database, .. } => database,
+            Api::V2 { ref bucket, .. } => bucket
+        }
+    }
+
+    fn auth<'a>(&'a self) -> Auth<'a> {
+        match *self {
+            Api::V1 { ref username, ref password, .. } => Auth::Basic { username, password },
+            Api::V2 { ref token, .. } => Auth::Token(token)
+        }
+    }
+
+    /// Checks that write options are supported by the API.
+    fn validate(&self, options: &WriteOptions) -> Result<(), ClientError> {
+        if let Api::V1 { .. } = *self {
+            return Ok(());
+        }
+
+        if let Some(precision) = options.precision.filter(|precision| precision.as_v2_str().is_none()) {
+            return Err(ClientError::InvalidOptions(format!("precision {:?} is not supported by InfluxDB 2.x", precision)));
+        }
+
+        if options.retention_policy.is_some() || options.consistency.is_some() {
+            return Err(ClientError::InvalidOptions("retention_policy and consistency are not supported by InfluxDB 2.x".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn write_path(&self) -> &'static str {
+        match *self {
+            Api::V1 { .. } => "/write",
+            Api::V2 { .. } => "/api/v2/write"
+        }
+    }
 
-            if let Some(ref precision) = precision {
-                query.insert("precision", precision.to_string());
+    fn write_query(&self, options: &WriteOptions) -> HashMap<&'static str, String> {
+        let mut query = HashMap::new();
+
+        match *self {
+            Api::V1 { ref database, .. } => {
+                query.insert("db", database.clone());
+
+                if let Some(ref precision) = options.precision {
+                    query.insert("precision", precision.to_string());
+                }
+
+                if let Some(ref rp) = options.retention_policy {
+                    query.insert("rp", rp.clone());
+                }
+
+                if let Some(ref consistency) = options.consistency {
+                    query.insert("consistency", consistency.to_string());
+                }
+            },
+            Api::V2 { ref org, ref bucket, .. } => {
+                query.insert("org", org.clone());
+                query.insert("bucket", bucket.clone());
+
+                if let Some(precision) = options.precision.and_then(|precision| precision.as_v2_str()) {
+                    query.insert("precision", precision.to_string());
+                }
             }
+        }
 
-            let request = Request {
-                url: &*{host.to_string() + "/write"},
-                method: Method::POST,
-                auth: Some(Auth {
-                    username: self.credentials.username,
-                    password: self.credentials.password
-                }),
-                query: Some(query),
-                body: Some(lines.join("\n"))
-            };
+        query
+    }
+}
+
+fn write_result(res: Result<Response, HurlError>) -> Result<(), ClientError> {
+    match res {
+        Ok(ref resp) if resp.status == 204 => Ok(()),
+        Ok(ref resp) if resp.status == 200 => Err(ClientError::CouldNotComplete(server_error(resp))),
+        Ok(ref resp) if resp.status == 400 => Err(ClientError::Syntax(server_error(resp))),
+        Ok(ref resp) => Err(ClientError::Unexpected(server_error(resp))),
+        Err(reason) => Err(transport_error(reason))
+    }
+}
+
+#[derive(Serialize)]
+struct FluxRequest<'a> {
+    query: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    dialect: FluxDialect
+}
+
+#[derive(Serialize)]
+struct FluxDialect {
+    header: bool,
+    annotations: [&'static str; 3]
+}
+
+fn flux_request(script: &str) -> String {
+    let request = FluxRequest {
+        query: script,
+        kind: "flux",
+        dialect: FluxDialect {
+            header: true,
+            annotations: ["datatype", "group", "default"]
+        }
+    };
+
+    serde_json::to_string(&request).expect("flux request is always serializable")
+}
+
+fn flux_tables(resp: Response) -> Result<Vec<Table>, ClientError> {
+    let tables = csv::parse_annotated(&resp.body).map_err(|e| ClientError::Parse(format!("Could not parse response: {}; Body: \"{}\"", e, resp.body)))?;
+
+    match tables.iter().find_map(|table| table.error()) {
+        Some(message) => Err(ClientError::Unexpected(ServerError::with_message(resp.status, message.to_string(), &resp.body))),
+        None => Ok(tables)
+    }
+}
+
+/// Replaces syntax error with the partial write, if server rejected only some lines of the batch.
+fn partial_write(e: ClientError, batch: &[String], offset: usize) -> ClientError {
+    match e {
+        ClientError::Syntax(e) => match PartialWrite::parse(e, batch, offset) {
+            Ok(partial) => ClientError::PartialWrite(partial),
+            Err(e) => ClientError::Syntax(e)
+        },
+        e => e
+    }
+}
+
+fn server_error(resp: &Response) -> ServerError {
+    ServerError::new(resp.status, &resp.headers, &resp.body)
+}
+
+fn transport_error(e: HurlError) -> ClientError {
+    match e.kind() {
+        ErrorKind::Timeout => ClientError::Timeout(e),
+        _ => ClientError::Communication(e)
+    }
+}
+
+/// Fails with the timeout error, if future does not complete in time.
+fn limit<T>(f: Box<dyn Future<Item=T, Error=HurlError> + Send>, timeout: Option<Duration>) -> Box<dyn Future<Item=T, Error=HurlError> + Send>
+    where T: Send + 'static
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return f
+    };
+
+    Box::new(Timeout::new(f, timeout).map_err(move |e| {
+        if e.is_elapsed() {
+            HurlError::new(ErrorKind::Timeout, format!("no response in {:?}", timeout))
+        } else if e.is_timer() {
+            HurlError::new(ErrorKind::Other, format!("timer error: {}", e))
+        } else {
+            e.into_inner().unwrap_or_else(|| HurlError::new(ErrorKind::Other, "request failed"))
+        }
+    }))
+}
+
+/// Fails with the timeout error, if stream does not end in time.
+fn deadline<S>(stream: S, timeout: Option<Duration>) -> ClientQueryStream
+    where S: Stream<Item=QueryResponse, Error=ClientError> + Send + 'static
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Box::new(stream)
+    };
+
+    let mut stream = stream;
+    let mut delay = Delay::new(Instant::now() + timeout);
+
+    Box::new(stream::poll_fn(move || {
+        match delay.poll() {
+            Ok(Async::NotReady) => stream.poll(),
+            Ok(Async::Ready(())) => Err(ClientError::Timeout(HurlError::new(ErrorKind::Timeout, format!("query did not complete in {:?}", timeout)))),
+            Err(e) => Err(ClientError::Communication(HurlError::with_source(ErrorKind::Other, "timer error", e)))
+        }
+    }))
+}
+
+/// Request which could be sent to any of the hosts.
+struct Template {
+    method: Method,
+    path: &'static str,
+    api: Arc<Api>,
+    query: HashMap<&'static str, String>,
+    headers: HashMap<&'static str, String>,
+    body: Option<String>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>
+}
 
-            self.hurl.request(request).then(|res| {
-                match res {
-                    Ok(ref resp) if resp.status == 204 => Ok(()),
-                    Ok(ref resp) if resp.status == 200 => Err(ClientError::CouldNotComplete(resp.to_string())),
-                    Ok(ref resp) if resp.status == 400 => Err(ClientError::Syntax(resp.to_string())),
-                    Ok(ref resp) => Err(ClientError::Unexpected(format!("Unexpected response. Status: {}; Body: \"{}\"", resp.status, resp.to_string()))),
-                    Err(reason) => Err(ClientError::Communication(reason))
+impl Template {
+    fn request<'a>(&'a self, url: &'a str) -> Request<'a> {
+        Request {
+            url,
+            method: self.method,
+            auth: Some(self.api.auth()),
+            query: Some(self.query.clone()),
+            headers: Some(self.headers.clone()),
+            body: self.body.clone(),
+            connect_timeout: self.connect_timeout
+        }
+    }
+}
+
+/// Sends requests to the hosts of the client.
+#[derive(Clone)]
+struct Sender {
+    hurl: Arc<dyn Hurl + Send + Sync>,
+    hosts: Arc<Hosts>,
+    balance: Balance,
+    cooldown: Duration
+}
+
+impl Sender {
+    fn send(&self, template: Arc<Template>) -> HurlResult {
+        let hurl = self.hurl.clone();
+
+        failover(self.hosts.clone(), self.balance, self.cooldown, move |host| {
+            limit(hurl.request(template.request(&(host.to_string() + template.path))), template.timeout)
+        })
+    }
+
+    fn send_stream(&self, template: Arc<Template>) -> HurlStreamResult {
+        let hurl = self.hurl.clone();
+
+        failover(self.hosts.clone(), self.balance, self.cooldown, move |host| {
+            limit(hurl.stream(template.request(&(host.to_string() + template.path))), template.timeout)
+        })
+    }
+
+    /// Sends request, retrying it according to the retry policy.
+    fn send_retrying(&self, template: Arc<Template>, policy: RetryPolicy) -> HurlResult {
+        let sender = self.clone();
+
+        Box::new(future::loop_fn(1, move |attempt| {
+            let policy = policy.clone();
+
+            sender.send(template.clone()).then(move |res| {
+                if !policy.should_retry(attempt, &res) {
+                    return Either::A(future::ok(Loop::Break(res)));
                 }
+
+                let delay = Delay::new(Instant::now() + policy.delay(attempt, &res));
+
+                Either::B(delay.then(move |_| Ok(Loop::Continue(attempt + 1))))
             })
-        });
+        }).and_then(|res| res))
+    }
+}
+
+trait Status {
+    fn status(&self) -> u16;
+}
+
+impl Status for Response {
+    fn status(&self) -> u16 {
+        self.status
+    }
+}
 
-        Box::new(stream::futures_ordered(futures).for_each(|_| Ok(())))
+impl Status for StreamResponse {
+    fn status(&self) -> u16 {
+        self.status
     }
 }
 
+/// Sends request to the hosts one by one, until some of them responds without a server error.
+///
+/// Hosts which fail at the transport level or respond with 5xx status are marked unhealthy for the
+/// `cooldown` duration. Result of the last tried host is returned when all of them fail.
+fn failover<T, F>(hosts: Arc<Hosts>, balance: Balance, cooldown: Duration, send: F) -> Box<dyn Future<Item=T, Error=HurlError> + Send>
+    where T: Status + Send + 'static,
+          F: Fn(&str) -> Box<dyn Future<Item=T, Error=HurlError> + Send> + Send + 'static
+{
+    let order = hosts.order(balance);
+
+    if order.is_empty() {
+        return Box::new(future::err(HurlError::new(ErrorKind::Other, "No hosts to send request to")));
+    }
+
+    Box::new(future::loop_fn(0, move |i| {
+        let (index, ref host) = order[i];
+        let last = i + 1 == order.len();
+        let hosts = hosts.clone();
+        let in_flight = InFlight::new(hosts.clone(), index);
+
+        send(host).then(move |res| {
+            drop(in_flight);
+
+            let failed = match res {
+                Ok(ref resp) => resp.status() >= 500,
+                Err(_) => true
+            };
+
+            if !failed {
+                hosts.mark_healthy(index);
+                return Ok(Loop::Break(res));
+            }
+
+            hosts.mark_unhealthy(index, cooldown);
 
+            if last {
+                Ok(Loop::Break(res))
+            } else {
+                Ok(Loop::Continue(i + 1))
+            }
+        })
+    }).and_then(|res| res))
+}
 
 #[cfg(test)]
 mod tests {
     use ::serializer::Serializer;
     use ::client::{Client};
     use super::HttpClient;
-    use ::client::{Credentials, Precision};
-    use ::hurl::{Hurl, Request, Response, HurlResult};
+    use ::client::hosts::Balance;
+    use ::client::retry::RetryPolicy;
+    use ::client::spool::{Spool, SpoolOptions};
+    use ::client::{Credentials, TokenCredentials, FluxBody, QueryFormat, Precision, Consistency, ClientError, WriteOptions, QueryOptions};
+    use ::hurl::{Hurl, Request, Response, Method, Auth, StreamResponse, HurlResult, HurlStreamResult, HurlError, ErrorKind};
     use ::measurement::Measurement;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use ::futures::{self, Future};
+    use ::query::Value;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::env;
+    use std::fs;
+    use std::process;
+    use ::futures::{self, Future, Stream};
+    use ::tokio::timer::Delay;
 
     struct MockSerializer {
         serialize_count: AtomicUsize,
@@ -167,14 +884,14 @@ mod tests {
 
     struct MockHurl {
         request_count: AtomicUsize,
-        result: Box<(Fn() -> HurlResult) + Send + Sync>
+        result: Box<dyn Fn(&Request) -> HurlResult + Send + Sync>
     }
 
     impl MockHurl {
-        fn new(result: Box<(Fn() -> HurlResult) + Send + Sync>) -> MockHurl {
+        fn new(result: Box<dyn Fn(&Request) -> HurlResult + Send + Sync>) -> MockHurl {
             MockHurl {
                 request_count: AtomicUsize::new(0),
-                result: result
+                result
             }
         }
     }
@@ -183,12 +900,54 @@ mod tests {
         fn request(&self, req: Request) -> HurlResult {
             println!("sending: {:?}", req);
             self.request_count.fetch_add(1, Ordering::SeqCst);
-            let ref f = self.result;
-            f()
+            let f = &self.result;
+            f(&req)
+        }
+    }
+
+    /// Responds with the body, which never ends.
+    struct HungStream;
+
+    impl Hurl for HungStream {
+        fn request(&self, _: Request) -> HurlResult {
+            Box::new(futures::future::empty())
+        }
+
+        fn stream(&self, _: Request) -> HurlStreamResult {
+            let body = futures::future::empty().into_stream();
+            Box::new(futures::future::ok(StreamResponse { status: 200, headers: HashMap::new(), body: Box::new(body) }))
+        }
+    }
+
+    /// Responds with the binary body, which is available only as a stream.
+    struct BinaryHurl {
+        status: u16,
+        body: Vec<u8>
+    }
+
+    impl Hurl for BinaryHurl {
+        fn request(&self, _: Request) -> HurlResult {
+            panic!("binary body should be streamed")
+        }
+
+        fn stream(&self, req: Request) -> HurlStreamResult {
+            assert_eq!(Some(&"application/x-msgpack".to_string()), req.headers.as_ref().unwrap().get("Accept"));
+
+            let mut headers = HashMap::new();
+            if self.status != 200 {
+                headers.insert("x-influxdb-error".to_string(), "error parsing query".to_string());
+            }
+
+            let body = futures::stream::iter_ok(self.body.chunks(3).map(|chunk| chunk.to_vec()).collect::<Vec<_>>());
+            Box::new(futures::future::ok(StreamResponse { status: self.status, headers, body: Box::new(body) }))
         }
     }
 
-    fn before<'a>(result: Box<(Fn() -> HurlResult) + Send + Sync>) -> HttpClient<'a> {
+    fn respond(status: u16, body: &str) -> HurlResult {
+        Box::new(futures::future::ok(Response { status, headers: HashMap::new(), body: body.to_string() }))
+    }
+
+    fn before(result: Box<dyn Fn(&Request) -> HurlResult + Send + Sync>) -> HttpClient {
         let credentials = Credentials {
             username: "gobwas",
             password: "1234",
@@ -203,16 +962,754 @@ mod tests {
 
     #[test]
     fn test_write_one() {
-        let mut client = before(Box::new(|| Box::new(futures::future::ok(Response { status: 204, body: "Ok".to_string() }))));
+        let mut client = before(Box::new(|_| respond(204, "Ok")));
         client.add_host("http://localhost:8086");
-        ::tokio::run(client.write_one(Measurement::new("key"), Some(Precision::Nanoseconds)).map_err(|e| panic!(e)));
+        ::tokio::run(client.write_one(Measurement::new("key"), Some(WriteOptions { precision: Some(Precision::Nanoseconds), ..Default::default() })).map_err(|e| panic!("{:?}", e)));
     }
 
     #[test]
     fn test_write_many() {
-        let mut client = before(Box::new(|| Box::new(futures::future::ok(Response { status: 204, body: "Ok".to_string() }))));
+        let mut client = before(Box::new(|_| respond(204, "Ok")));
+        client.add_host("http://localhost:8086");
+        assert!(client.write_many(&[Measurement::new("key")], Some(WriteOptions { precision: Some(Precision::Nanoseconds), ..Default::default() })).wait().is_ok());
+    }
+
+    #[test]
+    fn test_write_many_options() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+
+        let mut client = before(Box::new(move |req| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(Some(&"ms".to_string()), req.query.as_ref().unwrap().get("precision"));
+            respond(204, "")
+        }));
+        client.add_host("http://localhost:8086");
+        client.write_options = WriteOptions { precision: Some(Precision::Seconds), max_batch: Some(2), ..Default::default() };
+
+        let measurements = vec![Measurement::new("a"), Measurement::new("b"), Measurement::new("c")];
+        let options = WriteOptions { precision: Some(Precision::Milliseconds), ..Default::default() };
+
+        assert!(client.write_many(&measurements, Some(options)).wait().is_ok());
+        assert_eq!(2, count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_write_retention_policy_and_consistency() {
+        let mut client = before(Box::new(|req| {
+            let query = req.query.as_ref().unwrap();
+            assert_eq!(Some(&"one_week".to_string()), query.get("rp"));
+            assert_eq!(Some(&"quorum".to_string()), query.get("consistency"));
+            respond(204, "")
+        }));
         client.add_host("http://localhost:8086");
-        assert!(client.write_many(&[Measurement::new("key")], Some(Precision::Nanoseconds)).wait().is_ok());
+        client.write_options.retention_policy = Some("one_week".to_string());
+
+        let options = WriteOptions { consistency: Some(Consistency::Quorum), ..Default::default() };
+
+        assert!(client.write_one(Measurement::new("key"), Some(options)).wait().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let mut client = before(Box::new(|_| panic!("request should not be sent")));
+        client.add_host("http://localhost:8086");
+
+        let options = WriteOptions { retention_policy: Some("".to_string()), ..Default::default() };
+
+        match client.write_one(Measurement::new("key"), Some(options)).wait() {
+            Err(ClientError::InvalidOptions(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        let options = WriteOptions { max_batch: Some(0), ..Default::default() };
+
+        match client.write_one(Measurement::new("key"), Some(options)).wait() {
+            Err(ClientError::InvalidOptions(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        let options = QueryOptions { chunk_size: Some(0), ..Default::default() };
+
+        match client.query_chunked("select * from key".to_string(), Some(options)).collect().wait() {
+            Err(ClientError::InvalidOptions(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_write_v2() {
+        let credentials = TokenCredentials { token: "secret", org: "gobwas", bucket: "test" };
+        let hurl = MockHurl::new(Box::new(|req| {
+            let query = req.query.as_ref().unwrap();
+            assert_eq!("http://localhost:8086/api/v2/write", req.url);
+            assert_eq!(Some(&"gobwas".to_string()), query.get("org"));
+            assert_eq!(Some(&"test".to_string()), query.get("bucket"));
+            assert_eq!(Some(&"us".to_string()), query.get("precision"));
+            assert_eq!(None, query.get("db"));
+
+            match req.auth {
+                Some(Auth::Token("secret")) => respond(204, ""),
+                ref auth => panic!("unexpected auth: {:?}", auth)
+            }
+        }));
+
+        let mut client = HttpClient::with_token(credentials, Box::new(MockSerializer::new()), Box::new(hurl));
+        client.add_host("http://localhost:8086");
+
+        let options = WriteOptions { precision: Some(Precision::Microseconds), ..Default::default() };
+
+        assert!(client.write_one(Measurement::new("key"), Some(options)).wait().is_ok());
+    }
+
+    #[test]
+    fn test_write_v2_unsupported_options() {
+        let credentials = TokenCredentials { token: "secret", org: "gobwas", bucket: "test" };
+        let hurl = MockHurl::new(Box::new(|_| panic!("request should not be sent")));
+
+        let mut client = HttpClient::with_token(credentials, Box::new(MockSerializer::new()), Box::new(hurl));
+        client.add_host("http://localhost:8086");
+
+        for options in [
+            WriteOptions { precision: Some(Precision::Minutes), ..Default::default() },
+            WriteOptions { retention_policy: Some("autogen".to_string()), ..Default::default() },
+            WriteOptions { consistency: Some(Consistency::All), ..Default::default() }
+        ] {
+            match client.write_one(Measurement::new("key"), Some(options)).wait() {
+                Err(ClientError::InvalidOptions(_)) => {},
+                res => panic!("unexpected result: {:?}", res)
+            }
+        }
+    }
+
+    const FLUX_BODY: &str = "#datatype,string,long,double\r\n#group,false,false,false\r\n#default,_result,,\r\n,result,table,_value\r\n,,0,1.5\r\n\r\n";
+
+    #[test]
+    fn test_query_flux() {
+        let credentials = TokenCredentials { token: "secret", org: "gobwas", bucket: "test" };
+        let hurl = MockHurl::new(Box::new(|req| {
+            let headers = req.headers.as_ref().unwrap();
+            assert_eq!("http://localhost:8086/api/v2/query", req.url);
+            assert_eq!(Method::POST, req.method);
+            assert_eq!(Some(&"gobwas".to_string()), req.query.as_ref().unwrap().get("org"));
+            assert_eq!(Some(&"application/json".to_string()), headers.get("Content-Type"));
+            assert_eq!(Some(&"application/csv".to_string()), headers.get("Accept"));
+
+            let body: ::serde_json::Value = ::serde_json::from_str(req.body.as_ref().unwrap()).unwrap();
+            assert_eq!("buckets()", body["query"]);
+            assert_eq!("flux", body["type"]);
+            assert_eq!(3, body["dialect"]["annotations"].as_array().unwrap().len());
+
+            respond(200, FLUX_BODY)
+        }));
+
+        let mut client = HttpClient::with_token(credentials, Box::new(MockSerializer::new()), Box::new(hurl));
+        client.add_host("http://localhost:8086");
+
+        let tables = client.query_flux("buckets()".to_string(), None).wait().unwrap();
+
+        assert_eq!(1, tables.len());
+        assert_eq!(vec![vec![Value::from("_result"), Value::from(0), Value::from(1.5)]], tables[0].records);
+    }
+
+    #[test]
+    fn test_query_flux_plain() {
+        let mut client = before(Box::new(|req| {
+            assert_eq!(Some(&"application/vnd.flux".to_string()), req.headers.as_ref().unwrap().get("Content-Type"));
+            assert_eq!(Some("buckets()"), req.body.as_deref());
+            assert_eq!(None, req.query.as_ref().unwrap().get("org"));
+            respond(200, FLUX_BODY)
+        }));
+        client.add_host("http://localhost:8086");
+        client.flux_options.body = Some(FluxBody::Flux);
+
+        assert!(client.query_flux("buckets()".to_string(), None).wait().is_ok());
+    }
+
+    #[test]
+    fn test_query_flux_errors() {
+        let mut client = before(Box::new(|req| match req.body.as_deref() {
+            Some(body) if body.contains("runtime") => respond(200, "#datatype,string,string\n#group,true,true\n#default,,\n,error,reference\n,runtime error,\n"),
+            _ => respond(400, "{\"code\":\"invalid\",\"message\":\"compilation failed\"}")
+        }));
+        client.add_host("http://localhost:8086");
+
+        match client.query_flux("runtime".to_string(), None).wait() {
+            Err(ClientError::Unexpected(ref e)) => assert_eq!("runtime error", e.message),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        match client.query_flux("compile".to_string(), None).wait() {
+            Err(ClientError::Syntax(ref e)) => assert_eq!("compilation failed", e.message),
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_query_default_options() {
+        let mut client = before(Box::new(|req| {
+            let query = req.query.as_ref().unwrap();
+            assert_eq!(Some(&"h".to_string()), query.get("epoch"));
+            assert_eq!(Some(&"100".to_string()), query.get("chunk_size"));
+            assert_eq!(Some(&"true".to_string()), query.get("chunked"));
+            assert_eq!(Some(&"autogen".to_string()), query.get("rp"));
+            respond(200, "")
+        }));
+        client.add_host("http://localhost:8086");
+        client.query_options = QueryOptions { epoch: Some(Precision::Hours), chunk_size: Some(100), retention_policy: Some("autogen".to_string()), ..Default::default() };
+
+        assert!(client.query_chunked("select * from key".to_string(), None).collect().wait().is_ok());
+    }
+
+    #[test]
+    fn test_failover() {
+        let urls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = urls.clone();
+
+        let mut client = before(Box::new(move |req| {
+            recorded.lock().unwrap().push(req.url.to_string());
+
+            match req.url {
+                "http://a/write" => respond(503, ""),
+                "http://b/write" => Box::new(futures::future::err(HurlError::new(ErrorKind::Connect, "connection refused"))),
+                _ => respond(204, "")
+            }
+        }));
+        client.add_host("http://a");
+        client.add_host("http://b");
+        client.add_host("http://c");
+
+        assert!(client.write_one(Measurement::new("key"), None).wait().is_ok());
+        assert!(client.write_one(Measurement::new("key"), None).wait().is_ok());
+
+        assert_eq!(vec!["http://a/write", "http://b/write", "http://c/write", "http://c/write"], *urls.lock().unwrap());
+    }
+
+    #[test]
+    fn test_failover_cooldown() {
+        let urls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = urls.clone();
+
+        let mut client = before(Box::new(move |req| {
+            recorded.lock().unwrap().push(req.url.to_string());
+            let status = if recorded.lock().unwrap().len() == 1 { 500 } else { 204 };
+            respond(status, "")
+        }));
+        client.add_host("http://a");
+        client.add_host("http://b");
+        client.host_cooldown = Duration::from_secs(0);
+
+        assert!(client.write_one(Measurement::new("key"), None).wait().is_ok());
+        assert!(client.write_one(Measurement::new("key"), None).wait().is_ok());
+
+        assert_eq!(vec!["http://a/write", "http://b/write", "http://a/write"], *urls.lock().unwrap());
+    }
+
+    #[test]
+    fn test_write_retry() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+
+        let mut client = before(Box::new(move |_| {
+            match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => Box::new(futures::future::err(HurlError::new(ErrorKind::Connect, "connection refused"))),
+                1 => respond(503, ""),
+                _ => respond(204, "")
+            }
+        }));
+        client.add_host("http://localhost:8086");
+        client.retry_policy = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::new(3)
+        };
+
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        assert!(rt.block_on(client.write_one(Measurement::new("key"), None)).is_ok());
+        assert_eq!(3, count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_write_retry_exhausted() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+
+        let mut client = before(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            respond(500, "")
+        }));
+        client.add_host("http://localhost:8086");
+        client.retry_policy = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::new(2)
+        };
+
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        match rt.block_on(client.write_one(Measurement::new("key"), None)) {
+            Err(ClientError::Unexpected(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+        assert_eq!(2, count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_write_partial() {
+        let mut client = before(Box::new(|req| {
+            match req.body.as_deref() {
+                Some("key value=1i\nkey value=") => respond(400, "{\"error\":\"partial write: unable to parse 'key value=': missing field value dropped=0\"}"),
+                Some("key value=3i\nkey value=4i") => respond(204, ""),
+                _ => respond(400, "{\"error\":\"partial write: field type conflict: input field \\\"value\\\" on measurement \\\"key\\\" is type float, already exists as type integer dropped=1\"}")
+            }
+        }));
+        client.add_host("http://localhost:8086");
+
+        let lines: Vec<String> = vec!["key value=1i", "key value=", "key value=3i", "key value=4i", "key value=5.5"].into_iter().map(String::from).collect();
+
+        match client.write_lines(&lines, Some(WriteOptions { max_batch: Some(2), ..Default::default() })).wait() {
+            Err(ClientError::PartialWrite(partial)) => {
+                assert_eq!(2, partial.dropped);
+                assert_eq!(vec![1, 4], partial.rejected.iter().map(|r| r.index).collect::<Vec<_>>());
+                assert_eq!(2, partial.errors.len());
+            },
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_write_partial_and_failed() {
+        let mut client = before(Box::new(|req| {
+            match req.body.as_deref() {
+                Some("key value=1i\nkey value=") => respond(400, "{\"error\":\"partial write: unable to parse 'key value=': missing field value dropped=0\"}"),
+                Some("key value=3i\nkey value=4i") => respond(204, ""),
+                _ => respond(400, "{\"error\":\"unable to parse 'key': missing fields\"}")
+            }
+        }));
+        client.add_host("http://localhost:8086");
+
+        let lines: Vec<String> = vec!["key value=1i", "key value=", "key value=3i", "key value=4i", "key"].into_iter().map(String::from).collect();
+
+        match client.write_lines(&lines, Some(WriteOptions { max_batch: Some(2), ..Default::default() })).wait() {
+            Err(ClientError::PartialFailure(e, partial)) => {
+                assert_eq!(Some(400), e.status());
+                assert_eq!(vec![1], partial.rejected.iter().map(|r| r.index).collect::<Vec<_>>());
+            },
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_write_no_retry_on_syntax_error() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+
+        let mut client = before(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            respond(400, "{\"error\":\"unable to parse 'key': missing fields\"}")
+        }));
+        client.add_host("http://localhost:8086");
+        client.retry_policy = RetryPolicy::new(5);
+
+        match client.write_one(Measurement::new("key"), None).wait() {
+            Err(ClientError::Syntax(ref e)) => {
+                assert_eq!(400, e.status);
+                assert_eq!("unable to parse 'key': missing fields", e.message);
+            },
+            res => panic!("unexpected result: {:?}", res)
+        }
+        assert_eq!(1, count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_write_spooled_and_replayed() {
+        let available = Arc::new(AtomicBool::new(false));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let (up, recorded) = (available.clone(), bodies.clone());
+
+        let mut client = before(Box::new(move |req| {
+            if !up.load(Ordering::SeqCst) {
+                return Box::new(futures::future::err(HurlError::new(ErrorKind::Connect, "connection refused")));
+            }
+
+            assert_eq!(Some("ms"), req.query.as_ref().unwrap().get("precision").map(|v| v.as_str()));
+            recorded.lock().unwrap().push(req.body.clone().unwrap());
+            respond(204, "")
+        }));
+        client.add_host("http://localhost:8086");
+
+        let dir = env::temp_dir().join(format!("influent-http-spool-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        client.set_spool(Spool::open(&dir, SpoolOptions::default()).unwrap());
+
+        let options = WriteOptions { precision: Some(Precision::Milliseconds), ..Default::default() };
+
+        match client.write_many(&[Measurement::new("a"), Measurement::new("b")], Some(options)).wait() {
+            Err(ClientError::Spooled(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        match client.replay().wait() {
+            Err(ClientError::Communication(ref e)) => assert_eq!(ErrorKind::Connect, e.kind()),
+            res => panic!("unexpected result: {:?}", res)
+        }
+        assert!(bodies.lock().unwrap().is_empty());
+
+        available.store(true, Ordering::SeqCst);
+
+        assert_eq!(1, client.replay().wait().unwrap());
+        assert_eq!(0, client.replay().wait().unwrap());
+        assert_eq!(vec!["serialized\nserialized"], *bodies.lock().unwrap());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replay_dropped() {
+        let available = Arc::new(AtomicBool::new(false));
+        let up = available.clone();
+
+        let mut client = before(Box::new(move |_| {
+            if !up.load(Ordering::SeqCst) {
+                return Box::new(futures::future::err(HurlError::new(ErrorKind::Connect, "connection refused")));
+            }
+
+            respond(204, "")
+        }));
+        client.add_host("http://localhost:8086");
+
+        let dir = env::temp_dir().join(format!("influent-http-spool-dropped-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        client.set_spool(Spool::open(&dir, SpoolOptions::default()).unwrap());
+
+        assert!(client.write_one(Measurement::new("key"), None).wait().is_err());
+
+        available.store(true, Ordering::SeqCst);
+        drop(client.replay());
+
+        assert_eq!(1, client.replay().wait().unwrap());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replay_with_batch_options() {
+        let available = Arc::new(AtomicBool::new(false));
+        let up = available.clone();
+
+        let mut client = before(Box::new(move |_| {
+            if !up.load(Ordering::SeqCst) {
+                return Box::new(futures::future::err(HurlError::new(ErrorKind::Connect, "connection refused")));
+            }
+
+            Box::new(futures::future::empty())
+        }));
+        client.add_host("http://localhost:8086");
+        client.write_options.request_timeout = Some(Duration::from_secs(60));
+
+        let dir = env::temp_dir().join(format!("influent-http-spool-options-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        client.set_spool(Spool::open(&dir, SpoolOptions::default()).unwrap());
+
+        let options = WriteOptions { request_timeout: Some(Duration::from_millis(10)), ..Default::default() };
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        assert!(rt.block_on(client.write_one(Measurement::new("key"), Some(options))).is_err());
+
+        available.store(true, Ordering::SeqCst);
+        let started = Instant::now();
+
+        match rt.block_on(client.replay()) {
+            Err(ClientError::Timeout(_)) => assert!(started.elapsed() < Duration::from_secs(10)),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_write_spools_all_failed_batches() {
+        let mut client = before(Box::new(|req| {
+            let refused = || HurlError::new(ErrorKind::Connect, "connection refused");
+
+            match req.body.as_deref() {
+                Some("k i=0i") => Box::new(futures::future::err(refused())),
+                _ => Box::new(Delay::new(Instant::now() + Duration::from_millis(50)).then(move |_| Err(refused())))
+            }
+        }));
+        client.add_host("http://localhost:8086");
+
+        let dir = env::temp_dir().join(format!("influent-http-spool-batches-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        client.set_spool(Spool::open(&dir, SpoolOptions::default()).unwrap());
+
+        let lines = vec!["k i=0i".to_string(), "k i=1i".to_string()];
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        match rt.block_on(client.write_lines(&lines, Some(WriteOptions { max_batch: Some(1), ..Default::default() }))) {
+            Err(ClientError::Spooled(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        let spool = client.spool.clone().unwrap();
+        let mut spooled = Vec::new();
+
+        while let Some(batch) = spool.front().unwrap() {
+            spool.ack(&batch).unwrap();
+            spooled.push(batch.body);
+        }
+        assert_eq!(vec!["k i=0i", "k i=1i"], spooled);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_futures_outlive_client() {
+        let mut client = before(Box::new(|_| respond(204, "")));
+        client.add_host("http://localhost:8086");
+
+        let clone = client.clone();
+        let write = client.write_one(Measurement::new(String::from("key")), None);
+        drop(client);
+
+        ::tokio::run(write.join(clone.write_one(Measurement::new("key"), None)).map(|_| ()).map_err(|e| panic!("{:?}", e)));
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let mut client = before(Box::new(|req| {
+            if req.url.starts_with("http://hung") {
+                Box::new(futures::future::empty())
+            } else {
+                respond(204, "")
+            }
+        }));
+        client.add_host("http://hung:8086");
+        client.add_host("http://localhost:8086");
+        client.write_options.request_timeout = Some(Duration::from_millis(10));
+
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        assert!(rt.block_on(client.write_one(Measurement::new("key"), None)).is_ok());
+
+        let mut client = before(Box::new(|_| Box::new(futures::future::empty())));
+        client.add_host("http://hung:8086");
+
+        let options = QueryOptions { request_timeout: Some(Duration::from_millis(10)), ..Default::default() };
+
+        match rt.block_on(client.query("select 1".to_string(), Some(options))) {
+            Err(ClientError::Timeout(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        let timeouts = Arc::new(Mutex::new(Vec::new()));
+        let recorded = timeouts.clone();
+
+        let mut client = before(Box::new(move |req| {
+            recorded.lock().unwrap().push(req.connect_timeout);
+
+            match req.method {
+                Method::GET => respond(200, "{}"),
+                Method::POST => respond(204, "")
+            }
+        }));
+        client.add_host("http://localhost:8086");
+        client.write_options.connect_timeout = Some(Duration::from_secs(1));
+
+        let options = WriteOptions { connect_timeout: Some(Duration::from_millis(100)), ..Default::default() };
+
+        assert!(client.write_one(Measurement::new("key"), None).wait().is_ok());
+        assert!(client.write_one(Measurement::new("key"), Some(options)).wait().is_ok());
+        assert!(client.query("select * from key".to_string(), None).wait().is_ok());
+
+        assert_eq!(vec![Some(Duration::from_secs(1)), Some(Duration::from_millis(100)), None], *timeouts.lock().unwrap());
+    }
+
+    #[test]
+    fn test_total_timeout() {
+        let mut client = before(Box::new(|_| respond(503, "")));
+        client.add_host("http://localhost:8086");
+        client.retry_policy = RetryPolicy {
+            base_delay: Duration::from_millis(50),
+            ..RetryPolicy::new(10)
+        };
+
+        let options = WriteOptions { total_timeout: Some(Duration::from_millis(20)), ..Default::default() };
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        match rt.block_on(client.write_one(Measurement::new("key"), Some(options))) {
+            Err(ClientError::Timeout(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_query_chunked_total_timeout() {
+        let mut client = HttpClient::new(
+            Credentials { username: "gobwas", password: "1234", database: "test" },
+            Box::new(MockSerializer::new()),
+            Box::new(HungStream)
+        );
+        client.add_host("http://localhost:8086");
+
+        let options = QueryOptions { total_timeout: Some(Duration::from_millis(10)), ..Default::default() };
+        let mut rt = ::tokio::runtime::current_thread::Runtime::new().unwrap();
+
+        match rt.block_on(client.query_chunked("select 1".to_string(), Some(options)).collect()) {
+            Err(ClientError::Timeout(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let urls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = urls.clone();
+
+        let mut client = before(Box::new(move |req| {
+            recorded.lock().unwrap().push(req.url.to_string());
+            respond(200, "{}")
+        }));
+        client.add_host("http://a");
+        client.add_host("http://b");
+        client.balance = Balance::RoundRobin;
+
+        for _ in 0..3 {
+            assert!(client.query("select * from key".to_string(), None).wait().is_ok());
+        }
+
+        assert_eq!(vec!["http://a/query", "http://b/query", "http://a/query"], *urls.lock().unwrap());
+    }
+
+    #[test]
+    fn test_failover_all_hosts_failed() {
+        let mut client = before(Box::new(|_| respond(500, "")));
+        client.add_host("http://a");
+        client.add_host("http://b");
+
+        match client.write_one(Measurement::new("key"), None).wait() {
+            Err(ClientError::Unexpected(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        let client = before(Box::new(|_| panic!("request should not be sent")));
+
+        match client.write_one(Measurement::new("key"), None).wait() {
+            Err(ClientError::Communication(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_query_results() {
+        let body = "{\"results\":[{\"statement_id\":0,\"series\":[{\"name\":\"key\",\"columns\":[\"time\",\"value\"],\"values\":[[1,10]]}]}]}";
+        let mut client = before(Box::new(move |_| respond(200, body)));
+        client.add_host("http://localhost:8086");
+
+        let response = client.query_results("select * from key".to_string(), Some(QueryOptions { epoch: Some(Precision::Seconds), ..Default::default() })).wait().unwrap();
+
+        assert_eq!("key", response.results[0].series[0].name);
+        assert_eq!(vec!["time", "value"], response.results[0].series[0].columns);
+    }
+
+    #[test]
+    fn test_query_chunked() {
+        let body = "{\"results\":[{\"statement_id\":0,\"series\":[{\"name\":\"key\",\"columns\":[\"time\",\"value\"],\"values\":[[1,10]],\"partial\":true}],\"partial\":true}]}\n{\"results\":[{\"statement_id\":0,\"series\":[{\"name\":\"key\",\"columns\":[\"time\",\"value\"],\"values\":[[2,20]]}]}]}\n";
+        let mut client = before(Box::new(move |_| respond(200, body)));
+        client.add_host("http://localhost:8086");
+
+        let chunks = client.query_chunked("select * from key".to_string(), Some(QueryOptions { chunk_size: Some(1), ..Default::default() })).collect().wait().unwrap();
+
+        assert_eq!(2, chunks.len());
+        assert!(chunks[0].results[0].partial);
+        assert!(!chunks[1].results[0].partial);
+        assert_eq!(vec![vec![Value::from(2), Value::from(20)]], chunks[1].results[0].series[0].values);
+    }
+
+    #[test]
+    fn test_query_results_csv() {
+        let mut client = before(Box::new(|req| {
+            assert_eq!(Some(&"application/csv".to_string()), req.headers.as_ref().unwrap().get("Accept"));
+            respond(200, "name,tags,time,value\ncpu,host=a,10,0.5\n")
+        }));
+        client.add_host("http://localhost:8086");
+
+        let options = QueryOptions { format: Some(QueryFormat::Csv), ..Default::default() };
+        let response = client.query_results("select * from cpu".to_string(), Some(options.clone())).wait().unwrap();
+
+        assert_eq!("a", response.results[0].series[0].tags["host"]);
+        assert_eq!(vec![vec![Value::from(10), Value::from(0.5)]], response.results[0].series[0].values);
+
+        match client.query_chunked("select * from cpu".to_string(), Some(options)).collect().wait() {
+            Err(ClientError::InvalidOptions(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_query_results_msgpack() {
+        // {"results": [{"statement_id": 0, "series": [{"name": "cpu", "columns": ["value"], "values": [[0.5]]}]}]}
+        let body = vec![
+            0x81, 0xa7, b'r', b'e', b's', b'u', b'l', b't', b's', 0x91,
+            0x82, 0xac, b's', b't', b'a', b't', b'e', b'm', b'e', b'n', b't', b'_', b'i', b'd', 0x00,
+            0xa6, b's', b'e', b'r', b'i', b'e', b's', 0x91,
+            0x83, 0xa4, b'n', b'a', b'm', b'e', 0xa3, b'c', b'p', b'u',
+            0xa7, b'c', b'o', b'l', b'u', b'm', b'n', b's', 0x91, 0xa5, b'v', b'a', b'l', b'u', b'e',
+            0xa6, b'v', b'a', b'l', b'u', b'e', b's', 0x91, 0x91, 0xcb, 0x3f, 0xe0, 0, 0, 0, 0, 0, 0
+        ];
+
+        let credentials = Credentials { username: "gobwas", password: "1234", database: "test" };
+        let mut client = HttpClient::new(credentials, Box::new(MockSerializer::new()), Box::new(BinaryHurl { status: 200, body }));
+        client.add_host("http://localhost:8086");
+        client.query_options.format = Some(QueryFormat::Msgpack);
+
+        let response = client.query_results("select value from cpu".to_string(), None).wait().unwrap();
+
+        assert_eq!("cpu", response.results[0].series[0].name);
+        assert_eq!(vec![vec![Value::from(0.5)]], response.results[0].series[0].values);
+
+        match client.query("select value from cpu".to_string(), None).wait() {
+            Err(ClientError::InvalidOptions(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_query_results_msgpack_error() {
+        let credentials = Credentials { username: "gobwas", password: "1234", database: "test" };
+        let mut client = HttpClient::new(credentials, Box::new(MockSerializer::new()), Box::new(BinaryHurl { status: 400, body: vec![0x81, 0xc4, 0x00] }));
+        client.add_host("http://localhost:8086");
+
+        let options = QueryOptions { format: Some(QueryFormat::Msgpack), ..Default::default() };
+
+        match client.query_results("select".to_string(), Some(options)).wait() {
+            Err(ClientError::Syntax(ref e)) => assert_eq!("error parsing query", e.message),
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_query_chunked_syntax_error() {
+        let mut client = before(Box::new(|_| respond(400, "{\"error\":\"error parsing query\"}")));
+        client.add_host("http://localhost:8086");
+
+        match client.query_chunked("select".to_string(), None).collect().wait() {
+            Err(ClientError::Syntax(ref e)) => assert_eq!("error parsing query", e.message),
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_query_results_invalid_body() {
+        let mut client = before(Box::new(|_| respond(200, "not a json")));
+        client.add_host("http://localhost:8086");
+
+        match client.query_results("select * from key".to_string(), None).wait() {
+            Err(ClientError::Parse(_)) => {},
+            res => panic!("unexpected result: {:?}", res)
+        }
     }
 }
 
@@ -1,13 +1,26 @@
 use ::measurement::Measurement;
-use std::io;
-use futures::Future;
+use ::query::QueryResponse;
+use ::query::csv::Table;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use futures::{Future, Stream};
 
 pub mod http;
+pub mod hosts;
+pub mod retry;
+pub mod buffered;
+pub mod spool;
+mod error;
+
+pub use self::error::{ClientError, ServerError, ServerErrorKind, PartialWrite, RejectedLine};
 
 pub trait Client {
-    fn write_many(&self, &[Measurement], Option<Precision>) -> ClientWriteResult;
-    fn write_one(&self, Measurement, Option<Precision>) -> ClientWriteResult;
-    fn query(&self, String, Option<Precision>) -> ClientReadResult;
+    fn write_many(&self, measurements: &[Measurement], options: Option<WriteOptions>) -> ClientWriteResult;
+    fn write_one(&self, measurement: Measurement, options: Option<WriteOptions>) -> ClientWriteResult;
+    fn query(&self, q: String, options: Option<QueryOptions>) -> ClientReadResult;
+    fn query_results(&self, q: String, options: Option<QueryOptions>) -> ClientQueryResult;
+    fn query_chunked(&self, q: String, options: Option<QueryOptions>) -> ClientQueryStream;
 }
 
 pub struct Credentials<'a> {
@@ -16,6 +29,14 @@ pub struct Credentials<'a> {
     pub database: &'a str
 }
 
+/// Credentials of the InfluxDB 2.x API.
+pub struct TokenCredentials<'a> {
+    pub token: &'a str,
+    pub org: &'a str,
+    pub bucket: &'a str
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Precision {
     Nanoseconds,
     Microseconds,
@@ -25,8 +46,31 @@ pub enum Precision {
     Hours
 }
 
-impl ToString for Precision {
-    fn to_string(&self) -> String {
+impl Precision {
+    /// Returns name of the precision in the InfluxDB 2.x API, which supports only precisions from
+    /// nanoseconds to seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::client::Precision;
+    ///
+    /// assert_eq!(Some("ns"), Precision::Nanoseconds.as_v2_str());
+    /// assert_eq!(None, Precision::Hours.as_v2_str());
+    /// ```
+    pub fn as_v2_str(&self) -> Option<&'static str> {
+        match *self {
+            Precision::Nanoseconds  => Some("ns"),
+            Precision::Microseconds => Some("us"),
+            Precision::Milliseconds => Some("ms"),
+            Precision::Seconds      => Some("s"),
+            Precision::Minutes | Precision::Hours => None
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match *self {
             Precision::Nanoseconds  => "n",
             Precision::Microseconds => "u",
@@ -36,26 +80,236 @@ impl ToString for Precision {
             Precision::Hours        => "h"
         };
 
-        s.to_string()
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Precision, String> {
+        match s {
+            "n"  => Ok(Precision::Nanoseconds),
+            "u"  => Ok(Precision::Microseconds),
+            "ms" => Ok(Precision::Milliseconds),
+            "s"  => Ok(Precision::Seconds),
+            "m"  => Ok(Precision::Minutes),
+            "h"  => Ok(Precision::Hours),
+            _    => Err(format!("unknown precision: {}", s))
+        }
+    }
+}
+
+/// Format of the `/query` response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryFormat {
+    Json,
+    /// CSV, which is cheaper to parse for the big results, but has no value types. Supported since
+    /// InfluxDB 1.8.
+    Csv,
+    /// MessagePack, which is the fastest to parse for the big numeric results. Its body is binary,
+    /// so it could be requested only with `query_results`.
+    Msgpack
+}
+
+/// Write consistency level of the InfluxDB Enterprise cluster.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Consistency {
+    Any,
+    One,
+    Quorum,
+    All
+}
+
+impl fmt::Display for Consistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Consistency::Any    => "any",
+            Consistency::One    => "one",
+            Consistency::Quorum => "quorum",
+            Consistency::All    => "all"
+        };
+
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Consistency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Consistency, String> {
+        match s {
+            "any"    => Ok(Consistency::Any),
+            "one"    => Ok(Consistency::One),
+            "quorum" => Ok(Consistency::Quorum),
+            "all"    => Ok(Consistency::All),
+            _        => Err(format!("unknown consistency: {}", s))
+        }
+    }
+}
+
+/// Options of the write request.
+///
+/// Options which are not set are taken from the client defaults.
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Precision of the measurements timestamps.
+    pub precision: Option<Precision>,
+
+    /// Maximum number of measurements sent in a single request.
+    pub max_batch: Option<u16>,
+
+    /// Retention policy to write to. If not set, the default retention policy of the database is used.
+    pub retention_policy: Option<String>,
+
+    /// Write consistency level. Used only by InfluxDB Enterprise clusters.
+    pub consistency: Option<Consistency>,
+
+    /// Maximum duration of establishing connection to a host. If not set, connect timeout of the
+    /// `Hurl` is used, such as `HyperOptions::connect_timeout`.
+    pub connect_timeout: Option<Duration>,
+
+    /// Maximum duration of a single request to a host. Request which timed out is retried or sent to
+    /// the next host.
+    pub request_timeout: Option<Duration>,
+
+    /// Maximum duration of the write of a batch, including retries and failover.
+    pub total_timeout: Option<Duration>
+}
+
+impl WriteOptions {
+    /// Returns options with fields which are not set taken from `defaults`.
+    pub fn or(self, defaults: &WriteOptions) -> WriteOptions {
+        WriteOptions {
+            precision: self.precision.or(defaults.precision),
+            max_batch: self.max_batch.or(defaults.max_batch),
+            retention_policy: self.retention_policy.or_else(|| defaults.retention_policy.clone()),
+            consistency: self.consistency.or(defaults.consistency),
+            connect_timeout: self.connect_timeout.or(defaults.connect_timeout),
+            request_timeout: self.request_timeout.or(defaults.request_timeout),
+            total_timeout: self.total_timeout.or(defaults.total_timeout)
+        }
+    }
+
+    /// Checks that options could be sent to the server.
+    pub fn validate(&self) -> Result<(), ClientError> {
+        if self.max_batch == Some(0) {
+            return Err(ClientError::InvalidOptions("max_batch must be greater than zero".to_string()));
+        }
+
+        validate_retention_policy(&self.retention_policy)
+    }
+}
+
+/// Options of the query request.
+///
+/// Options which are not set are taken from the client defaults.
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Precision of the returned timestamps. If not set, timestamps are returned in RFC3339 format.
+    pub epoch: Option<Precision>,
+
+    /// Number of rows per chunk of the chunked query.
+    pub chunk_size: Option<u16>,
+
+    /// Format of the response. If not set, JSON is used. Chunked queries support only JSON.
+    pub format: Option<QueryFormat>,
+
+    /// Retention policy used for measurements which are not fully qualified in the query.
+    pub retention_policy: Option<String>,
+
+    /// Maximum duration of establishing connection to a host. If not set, connect timeout of the
+    /// `Hurl` is used.
+    pub connect_timeout: Option<Duration>,
+
+    /// Maximum duration of a single request to a host, before the next host is tried. For chunked
+    /// queries, it limits waiting for the response headers.
+    pub request_timeout: Option<Duration>,
+
+    /// Maximum duration of the query, including failover and reading of all chunks.
+    pub total_timeout: Option<Duration>
+}
+
+impl QueryOptions {
+    /// Returns options with fields which are not set taken from `defaults`.
+    pub fn or(self, defaults: &QueryOptions) -> QueryOptions {
+        QueryOptions {
+            epoch: self.epoch.or(defaults.epoch),
+            chunk_size: self.chunk_size.or(defaults.chunk_size),
+            format: self.format.or(defaults.format),
+            retention_policy: self.retention_policy.or_else(|| defaults.retention_policy.clone()),
+            connect_timeout: self.connect_timeout.or(defaults.connect_timeout),
+            request_timeout: self.request_timeout.or(defaults.request_timeout),
+            total_timeout: self.total_timeout.or(defaults.total_timeout)
+        }
+    }
+
+    /// Checks that options could be sent to the server.
+    pub fn validate(&self) -> Result<(), ClientError> {
+        if self.chunk_size == Some(0) {
+            return Err(ClientError::InvalidOptions("chunk_size must be greater than zero".to_string()));
+        }
+
+        validate_retention_policy(&self.retention_policy)
     }
 }
 
-pub type ClientWriteResult = Box<Future<Item=(), Error=ClientError> + Send>;
+/// Encoding of the Flux query request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FluxBody {
+    /// JSON body, which asks for all annotations of the response.
+    Json,
+    /// Plain script with `application/vnd.flux` content type. Server responds in its default CSV
+    /// dialect, and columns without annotations are parsed as strings.
+    Flux
+}
+
+/// Options of the Flux query request.
+///
+/// Options which are not set are taken from the client defaults.
+#[derive(Debug, Clone, Default)]
+pub struct FluxOptions {
+    /// Encoding of the request. If not set, JSON is used.
+    pub body: Option<FluxBody>,
+
+    /// Maximum duration of establishing connection to a host. If not set, connect timeout of the
+    /// `Hurl` is used.
+    pub connect_timeout: Option<Duration>,
+
+    /// Maximum duration of a single request to a host, before the next host is tried.
+    pub request_timeout: Option<Duration>,
 
-// TODO: here parsing json?
-pub type ClientReadResult = Box<Future<Item=String, Error=ClientError> + Send>;
+    /// Maximum duration of the query, including failover.
+    pub total_timeout: Option<Duration>
+}
 
-#[derive(Debug)]
-pub enum ClientError {
-    CouldNotComplete(String),
-    Communication(String),
-    Syntax(String),
-    Unexpected(String),
-    Unknown
+impl FluxOptions {
+    /// Returns options with fields which are not set taken from `defaults`.
+    pub fn or(self, defaults: &FluxOptions) -> FluxOptions {
+        FluxOptions {
+            body: self.body.or(defaults.body),
+            connect_timeout: self.connect_timeout.or(defaults.connect_timeout),
+            request_timeout: self.request_timeout.or(defaults.request_timeout),
+            total_timeout: self.total_timeout.or(defaults.total_timeout)
+        }
+    }
 }
 
-impl From<io::Error> for ClientError {
-    fn from(e: io::Error) -> Self {
-        ClientError::Communication(format!("{}", e))
+fn validate_retention_policy(rp: &Option<String>) -> Result<(), ClientError> {
+    match *rp {
+        Some(ref rp) if rp.trim().is_empty() => Err(ClientError::InvalidOptions("retention_policy must not be empty".to_string())),
+        _ => Ok(())
     }
 }
+
+pub type ClientWriteResult = Box<dyn Future<Item=(), Error=ClientError> + Send>;
+
+pub type ClientReadResult = Box<dyn Future<Item=String, Error=ClientError> + Send>;
+
+pub type ClientQueryResult = Box<dyn Future<Item=QueryResponse, Error=ClientError> + Send>;
+
+pub type ClientFluxResult = Box<dyn Future<Item=Vec<Table>, Error=ClientError> + Send>;
+
+pub type ClientReplayResult = Box<dyn Future<Item=usize, Error=ClientError> + Send>;
+
+pub type ClientQueryStream = Box<dyn Stream<Item=QueryResponse, Error=ClientError> + Send>;
@@ -0,0 +1,163 @@
+use std::cmp;
+use std::time::Duration;
+use rand::{thread_rng, Rng};
+use ::hurl::{Response, HurlError};
+
+/// Policy of retrying failed write requests.
+///
+/// Request is retried when it fails at the transport level, or when server responds with 5xx or
+/// 429 status. Delay between attempts grows exponentially from `base_delay` up to `max_delay`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+
+    /// Delay before the first retry.
+    pub base_delay: Duration,
+
+    /// Maximum delay between attempts.
+    pub max_delay: Duration,
+
+    /// Whether to randomize delays, so clients do not retry at the same time.
+    pub jitter: bool,
+
+    /// Whether to wait as long as server asks in `Retry-After` header, up to the `max_delay`.
+    pub retry_after: bool
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            retry_after: true
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Constructs policy with given maximum number of attempts and default delays.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::client::retry::RetryPolicy;
+    ///
+    /// let policy = RetryPolicy::new(5);
+    ///
+    /// assert_eq!(5, policy.max_attempts);
+    /// ```
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Returns whether request, which was made `attempt` times with given result, should be retried.
+    pub fn should_retry(&self, attempt: u32, result: &Result<Response, HurlError>) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+
+        match *result {
+            Ok(ref resp) => resp.status >= 500 || resp.status == 429,
+            Err(_) => true
+        }
+    }
+
+    /// Returns delay before the next attempt, after request was made `attempt` times.
+    pub fn delay(&self, attempt: u32, result: &Result<Response, HurlError>) -> Duration {
+        if self.retry_after {
+            if let Some(delay) = retry_after(result) {
+                return cmp::min(delay, self.max_delay);
+            }
+        }
+
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = cmp::min(self.base_delay.checked_mul(factor).unwrap_or(self.max_delay), self.max_delay);
+
+        if self.jitter {
+            // pick random delay between a half and a whole of the computed one
+            let half = delay / 2;
+            half + half.mul_f64(thread_rng().gen::<f64>())
+        } else {
+            delay
+        }
+    }
+}
+
+fn retry_after(result: &Result<Response, HurlError>) -> Option<Duration> {
+    match *result {
+        Ok(ref resp) => resp.headers.get("retry-after").and_then(|v| v.trim().parse().ok()).map(Duration::from_secs),
+        Err(_) => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use ::hurl::{Response, HurlError, ErrorKind};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn response(status: u16, retry_after: Option<&str>) -> Result<Response, HurlError> {
+        let mut headers = HashMap::new();
+
+        if let Some(v) = retry_after {
+            headers.insert("retry-after".to_string(), v.to_string());
+        }
+
+        Ok(Response { status, headers, body: "".to_string() })
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new(3);
+
+        assert!(policy.should_retry(1, &response(500, None)));
+        assert!(policy.should_retry(1, &response(429, None)));
+        assert!(policy.should_retry(2, &Err(HurlError::new(ErrorKind::Connect, "connection refused"))));
+        assert!(!policy.should_retry(3, &response(503, None)));
+        assert!(!policy.should_retry(1, &response(400, None)));
+        assert!(!policy.should_retry(1, &response(204, None)));
+        assert!(!RetryPolicy::default().should_retry(1, &response(500, None)));
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter: false,
+            retry_after: true
+        };
+
+        assert_eq!(Duration::from_millis(100), policy.delay(1, &response(500, None)));
+        assert_eq!(Duration::from_millis(200), policy.delay(2, &response(500, None)));
+        assert_eq!(Duration::from_millis(800), policy.delay(4, &response(500, None)));
+        assert_eq!(Duration::from_secs(1), policy.delay(5, &response(500, None)));
+        assert_eq!(Duration::from_secs(1), policy.delay(40, &response(500, None)));
+        assert_eq!(Duration::from_secs(0), policy.delay(1, &response(429, Some("0"))));
+        assert_eq!(Duration::from_secs(1), policy.delay(1, &response(429, Some("120"))));
+        assert_eq!(Duration::from_millis(100), policy.delay(1, &response(429, Some("Wed, 21 Oct 2015 07:28:00 GMT"))));
+    }
+
+    #[test]
+    fn test_delay_jitter() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            ..RetryPolicy::new(3)
+        };
+
+        for _ in 0..10 {
+            let delay = policy.delay(2, &Err(HurlError::new(ErrorKind::Timeout, "timeout")));
+
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+    }
+}
@@ -0,0 +1,558 @@
+use ::client::WriteOptions;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use url::form_urlencoded;
+
+const MAX_BYTES: u64 = 512 * 1024 * 1024;
+const SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+const EXTENSION: &str = "spool";
+const HEADER: &str = "#batch";
+
+/// Options of the `Spool`.
+#[derive(Debug, Clone)]
+pub struct SpoolOptions {
+    /// Maximum size of the spool on disk. When exceeded, the oldest segments are dropped. Batch
+    /// larger than it could not be pushed.
+    pub max_bytes: u64,
+
+    /// Size of a segment file, after which the next segment is started.
+    pub segment_bytes: u64,
+
+    /// Whether to sync segment file to disk after each batch.
+    ///
+    /// Sync blocks the thread which pushes the batch, which is the thread of the executor for the
+    /// writes of `HttpClient`.
+    pub sync: bool
+}
+
+impl Default for SpoolOptions {
+    fn default() -> SpoolOptions {
+        SpoolOptions {
+            max_bytes: MAX_BYTES,
+            segment_bytes: SEGMENT_BYTES,
+            sync: true
+        }
+    }
+}
+
+/// Batch of lines stored in the spool.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    /// Line protocol body of the write request.
+    pub body: String,
+
+    /// Options the batch was written with.
+    pub options: WriteOptions,
+
+    // segment and position within it, where the batch was read
+    segment: u64,
+    offset: u64,
+    size: u64
+}
+
+/// Durable queue of batches which could not be written.
+///
+/// Batches are appended to segment files of line protocol text in the spool directory. Each batch is
+/// prefixed with a comment line holding its size and write options. Batches are read in order they
+/// were pushed, and a segment file is removed once all its batches are acknowledged.
+///
+/// When the size of the spool exceeds `max_bytes`, the oldest segments are dropped. Read position is
+/// kept in memory only, so after restart batches of a partially acknowledged segment are read again.
+pub struct Spool {
+    dir: PathBuf,
+    options: SpoolOptions,
+    state: Mutex<State>,
+    replaying: AtomicBool
+}
+
+struct State {
+    // sequence numbers of the segment files, the last one is being appended
+    segments: Vec<u64>,
+    // read position within the first segment
+    offset: u64,
+    // size of the unread data
+    bytes: u64,
+    // size of the last segment
+    tail: u64,
+    // sequence number of the next segment
+    next: u64
+}
+
+impl Spool {
+    /// Opens spool in the given directory, creating it if needed.
+    ///
+    /// Segments left in the directory are read first.
+    pub fn open<P: AsRef<Path>>(dir: P, options: SpoolOptions) -> io::Result<Spool> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut segments = Vec::new();
+        let mut bytes = 0;
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+
+            if let Some(seq) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+                bytes += fs::metadata(&path)?.len();
+                segments.push(seq);
+            }
+        }
+
+        segments.sort();
+
+        let (tail, next) = match segments.last() {
+            Some(seq) => (fs::metadata(segment_path(&dir, *seq))?.len(), seq + 1),
+            None => (0, 0)
+        };
+
+        Ok(Spool {
+            dir,
+            options,
+            state: Mutex::new(State {
+                segments,
+                offset: 0,
+                bytes,
+                tail,
+                next
+            }),
+            replaying: AtomicBool::new(false)
+        })
+    }
+
+    /// Returns size of the unread batches in bytes.
+    pub fn len(&self) -> u64 {
+        self.state.lock().unwrap().bytes
+    }
+
+    /// Returns `true` if there are no unread batches.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends batch to the spool.
+    pub fn push(&self, body: &str, options: &WriteOptions) -> io::Result<()> {
+        let record = format!("{} {} {}\n{}\n", HEADER, body.len(), encode_options(options), body);
+        let size = record.len() as u64;
+
+        if size > self.options.max_bytes {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("batch of {} bytes exceeds size of the spool", size)));
+        }
+
+        let file = {
+            let mut state = self.state.lock().unwrap();
+
+            // segment is also rotated when it alone would exceed the size, so it could be dropped
+            let rotate = state.segments.is_empty() || state.tail >= self.options.segment_bytes || state.tail + size > self.options.max_bytes;
+            let seq = match state.segments.last() {
+                Some(seq) if !rotate => *seq,
+                _ => state.next
+            };
+
+            let mut file = OpenOptions::new().create(true).append(true).open(segment_path(&self.dir, seq))?;
+
+            if rotate {
+                state.segments.push(seq);
+                state.next = seq + 1;
+                state.tail = 0;
+            }
+
+            file.write_all(record.as_bytes())?;
+
+            state.tail += size;
+            state.bytes += size;
+
+            while state.bytes > self.options.max_bytes && state.segments.len() > 1 {
+                self.drop_head(&mut state)?;
+            }
+
+            file
+        };
+
+        if self.options.sync {
+            file.sync_data()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the oldest unacknowledged batch.
+    ///
+    /// Segment which could not be parsed is dropped.
+    pub fn front(&self) -> io::Result<Option<Batch>> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            let seq = match state.segments.first() {
+                Some(seq) => *seq,
+                None => return Ok(None)
+            };
+
+            let mut file = File::open(segment_path(&self.dir, seq))?;
+            file.seek(SeekFrom::Start(state.offset))?;
+
+            match read_batch(&mut BufReader::new(file)) {
+                Ok(Some(mut batch)) => {
+                    batch.segment = seq;
+                    batch.offset = state.offset;
+                    return Ok(Some(batch));
+                },
+                Ok(None) if state.segments.len() == 1 => return Ok(None),
+                Ok(None) => self.drop_head(&mut state)?,
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => self.drop_head(&mut state)?,
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Acknowledges the batch returned by `front`, so the next one could be read.
+    ///
+    /// Does nothing if the batch was dropped meanwhile, or is already acknowledged.
+    pub fn ack(&self, batch: &Batch) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.segments.first() != Some(&batch.segment) || state.offset != batch.offset {
+            return Ok(());
+        }
+
+        state.offset += batch.size;
+        state.bytes = state.bytes.saturating_sub(batch.size);
+
+        let size = if state.segments.len() == 1 { state.tail } else { fs::metadata(segment_path(&self.dir, state.segments[0]))?.len() };
+
+        if state.offset >= size {
+            self.drop_head(&mut state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Marks spool as being replayed. Returns `false` if it is already replayed.
+    pub fn start_replay(&self) -> bool {
+        !self.replaying.swap(true, Ordering::SeqCst)
+    }
+
+    /// Marks spool as not being replayed.
+    pub fn stop_replay(&self) {
+        self.replaying.store(false, Ordering::SeqCst);
+    }
+
+    fn drop_head(&self, state: &mut State) -> io::Result<()> {
+        let seq = state.segments.remove(0);
+        let path = segment_path(&self.dir, seq);
+
+        let size = if state.segments.is_empty() { state.tail } else { fs::metadata(&path)?.len() };
+
+        state.bytes = state.bytes.saturating_sub(size.saturating_sub(state.offset));
+        state.offset = 0;
+
+        if state.segments.is_empty() {
+            state.tail = 0;
+        }
+
+        fs::remove_file(path)
+    }
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", seq, EXTENSION))
+}
+
+fn encode_options(options: &WriteOptions) -> String {
+    let mut query = form_urlencoded::Serializer::new(String::new());
+
+    if let Some(ref precision) = options.precision {
+        query.append_pair("precision", &precision.to_string());
+    }
+
+    if let Some(ref rp) = options.retention_policy {
+        query.append_pair("rp", rp);
+    }
+
+    if let Some(ref consistency) = options.consistency {
+        query.append_pair("consistency", &consistency.to_string());
+    }
+
+    if let Some(timeout) = options.connect_timeout {
+        query.append_pair("connect_timeout", &timeout.as_millis().to_string());
+    }
+
+    if let Some(timeout) = options.request_timeout {
+        query.append_pair("request_timeout", &timeout.as_millis().to_string());
+    }
+
+    if let Some(timeout) = options.total_timeout {
+        query.append_pair("total_timeout", &timeout.as_millis().to_string());
+    }
+
+    query.finish()
+}
+
+fn decode_options(query: &str) -> io::Result<WriteOptions> {
+    let mut options = WriteOptions::default();
+
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match &*key {
+            "precision" => options.precision = Some(value.parse().map_err(invalid)?),
+            "rp" => options.retention_policy = Some(value.into_owned()),
+            "consistency" => options.consistency = Some(value.parse().map_err(invalid)?),
+            "connect_timeout" => options.connect_timeout = Some(decode_millis(&value)?),
+            "request_timeout" => options.request_timeout = Some(decode_millis(&value)?),
+            "total_timeout" => options.total_timeout = Some(decode_millis(&value)?),
+            _ => {}
+        }
+    }
+
+    Ok(options)
+}
+
+fn decode_millis(value: &str) -> io::Result<Duration> {
+    value.parse().map(Duration::from_millis).map_err(|_| invalid(format!("invalid duration: {}", value)))
+}
+
+fn invalid(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn read_batch<R: BufRead>(reader: &mut R) -> io::Result<Option<Batch>> {
+    let mut header = String::new();
+
+    if reader.read_line(&mut header)? == 0 {
+        return Ok(None);
+    }
+
+    let mut parts = header.trim_end_matches('\n').splitn(3, ' ');
+
+    if parts.next() != Some(HEADER) {
+        return Err(invalid(format!("invalid batch header: {}", header)));
+    }
+
+    let len: usize = parts.next().and_then(|len| len.parse().ok()).ok_or_else(|| invalid(format!("invalid batch header: {}", header)))?;
+    let options = decode_options(parts.next().unwrap_or(""))?;
+
+    // body is followed by newline
+    let mut body = vec![0; len + 1];
+
+    if let Err(e) = reader.read_exact(&mut body) {
+        return match e.kind() {
+            io::ErrorKind::UnexpectedEof => Err(invalid("truncated batch".to_string())),
+            _ => Err(e)
+        };
+    }
+
+    body.pop();
+
+    Ok(Some(Batch {
+        body: String::from_utf8(body).map_err(|e| invalid(e.to_string()))?,
+        options,
+        segment: 0,
+        offset: 0,
+        size: (header.len() + len + 1) as u64
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Spool, SpoolOptions};
+    use ::client::{Precision, Consistency, WriteOptions};
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::Duration;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("influent-spool-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn pop(spool: &Spool) -> Option<String> {
+        spool.front().unwrap().map(|batch| {
+            spool.ack(&batch).unwrap();
+            batch.body
+        })
+    }
+
+    #[test]
+    fn test_push_front_ack() {
+        let dir = dir("order");
+        let spool = Spool::open(&dir, SpoolOptions::default()).unwrap();
+
+        assert!(spool.is_empty());
+        assert!(spool.front().unwrap().is_none());
+
+        let options = WriteOptions {
+            precision: Some(Precision::Milliseconds),
+            retention_policy: Some("one week".to_string()),
+            consistency: Some(Consistency::Quorum),
+            connect_timeout: Some(Duration::from_millis(250)),
+            request_timeout: Some(Duration::from_millis(1500)),
+            ..Default::default()
+        };
+
+        spool.push("a i=1i\na i=2i", &options).unwrap();
+        spool.push("b s=\"multi\nline\"", &WriteOptions::default()).unwrap();
+
+        let batch = spool.front().unwrap().unwrap();
+
+        assert_eq!("a i=1i\na i=2i", batch.body);
+        assert_eq!(Some(Precision::Milliseconds), batch.options.precision);
+        assert_eq!(Some("one week".to_string()), batch.options.retention_policy);
+        assert_eq!(Some(Consistency::Quorum), batch.options.consistency);
+        assert_eq!(Some(Duration::from_millis(250)), batch.options.connect_timeout);
+        assert_eq!(Some(Duration::from_millis(1500)), batch.options.request_timeout);
+        assert_eq!(None, batch.options.total_timeout);
+
+        // not acknowledged batch is returned again
+        assert_eq!(Some("a i=1i\na i=2i".to_string()), pop(&spool));
+        assert_eq!(Some("b s=\"multi\nline\"".to_string()), pop(&spool));
+        assert_eq!(None, pop(&spool));
+        assert!(spool.is_empty());
+
+        spool.push("c i=3i", &WriteOptions::default()).unwrap();
+
+        assert_eq!(Some("c i=3i".to_string()), pop(&spool));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = dir("reopen");
+        let options = SpoolOptions {
+            segment_bytes: 1,
+            ..SpoolOptions::default()
+        };
+
+        {
+            let spool = Spool::open(&dir, options.clone()).unwrap();
+
+            for i in 0..3 {
+                spool.push(&format!("key i={}i", i), &WriteOptions::default()).unwrap();
+            }
+
+            assert_eq!(Some("key i=0i".to_string()), pop(&spool));
+        }
+
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+
+        let spool = Spool::open(&dir, options).unwrap();
+
+        assert_eq!(Some("key i=1i".to_string()), pop(&spool));
+        assert_eq!(Some("key i=2i".to_string()), pop(&spool));
+        assert_eq!(None, pop(&spool));
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let dir = dir("drop");
+        let options = SpoolOptions {
+            max_bytes: 60,
+            segment_bytes: 20,
+            sync: false
+        };
+
+        let spool = Spool::open(&dir, options).unwrap();
+
+        for i in 0..5 {
+            spool.push(&format!("key i={}i", i), &WriteOptions::default()).unwrap();
+        }
+
+        assert!(spool.len() <= 60);
+        assert_eq!(Some("key i=2i".to_string()), pop(&spool));
+        assert_eq!(Some("key i=3i".to_string()), pop(&spool));
+        assert_eq!(Some("key i=4i".to_string()), pop(&spool));
+        assert_eq!(None, pop(&spool));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_oldest_of_single_segment() {
+        let dir = dir("drop-single");
+        let options = SpoolOptions {
+            max_bytes: 50,
+            sync: false,
+            ..SpoolOptions::default()
+        };
+
+        let spool = Spool::open(&dir, options).unwrap();
+
+        for i in 0..3 {
+            spool.push(&format!("key i={}i", i), &WriteOptions::default()).unwrap();
+        }
+
+        assert!(spool.len() <= 50);
+        assert_eq!(Some("key i=2i".to_string()), pop(&spool));
+        assert_eq!(None, pop(&spool));
+
+        assert!(spool.push(&"x".repeat(50), &WriteOptions::default()).is_err());
+        assert!(spool.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ack_of_dropped_batch() {
+        let dir = dir("ack-dropped");
+        let options = SpoolOptions {
+            max_bytes: 40,
+            segment_bytes: 1,
+            sync: false
+        };
+
+        let spool = Spool::open(&dir, options).unwrap();
+
+        spool.push("key i=0i", &WriteOptions::default()).unwrap();
+        spool.push("key i=1i", &WriteOptions::default()).unwrap();
+
+        let batch = spool.front().unwrap().unwrap();
+
+        // drops the segment of the batch being replayed
+        spool.push("key i=2i", &WriteOptions::default()).unwrap();
+        spool.ack(&batch).unwrap();
+        spool.ack(&batch).unwrap();
+
+        assert_eq!(Some("key i=1i".to_string()), pop(&spool));
+        assert_eq!(Some("key i=2i".to_string()), pop(&spool));
+        assert_eq!(None, pop(&spool));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_segment() {
+        let dir = dir("corrupted");
+        let options = SpoolOptions {
+            segment_bytes: 1,
+            ..SpoolOptions::default()
+        };
+
+        let spool = Spool::open(&dir, options.clone()).unwrap();
+
+        spool.push("key i=0i", &WriteOptions::default()).unwrap();
+        spool.push("key i=1i", &WriteOptions::default()).unwrap();
+
+        let first = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).min().unwrap();
+        OpenOptions::new().write(true).open(first).unwrap().write_all(b"garbage").unwrap();
+
+        let spool = Spool::open(&dir, options).unwrap();
+
+        assert_eq!(Some("key i=1i".to_string()), pop(&spool));
+        assert_eq!(None, pop(&spool));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,97 +1,421 @@
 use hyper::Client as HyperClient;
+use hyper::client::HttpConnector;
+use hyper::client::connect::dns::{Resolve, GaiResolver, GaiAddrs, GaiFuture, Name};
+#[cfg(feature = "tls")]
+use hyper_tls::HttpsConnector;
 use hyper::Method as HyperMethod;
 use hyper::Request as HyperRequest;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Error as HyperError;
+use std::error;
+use std::fmt;
+use std::io;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use http::header::AUTHORIZATION;
 use url::Url;
 use base64;
 use futures::{self, Future, Stream};
 
-use super::{Request, Response, Method, HurlResult};
+use super::{Auth, Request, Response, StreamResponse, Method, HurlResult, HurlStreamResult, HurlError, ErrorKind};
 
 use super::Hurl;
+#[cfg(feature = "tls")]
+use super::tls::TlsOptions;
 
-#[derive(Default)]
-pub struct HyperHurl;
+const DNS_THREADS: usize = 4;
 
-impl HyperHurl {
-    pub fn new() -> HyperHurl {
-        HyperHurl::default()
+const IDLE_TIMEOUT: u64 = 90;
+
+const CONNECT_TIMEOUT: u64 = 10;
+
+#[cfg(feature = "tls")]
+type Connector = HttpsConnector<HttpConnector<Resolver>>;
+
+#[cfg(not(feature = "tls"))]
+type Connector = HttpConnector<Resolver>;
+
+/// Failed lookup of a host name, wrapped so connect errors can tell it apart.
+#[derive(Debug)]
+struct DnsError(io::Error);
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not resolve host: {}", self.0)
     }
 }
 
-impl Hurl for HyperHurl {
-    fn request(&self, req: Request) -> HurlResult {
-        let client = HyperClient::default();
+impl error::Error for DnsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
 
-        // map request method to the hyper's
-        let method = match req.method {
-            Method::POST => HyperMethod::POST,
-            Method::GET  => HyperMethod::GET,
-        };
+fn dns_error(e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), DnsError(e))
+}
+
+/// Resolver that marks its failures as `DnsError`.
+#[derive(Clone)]
+struct Resolver(GaiResolver);
 
-        let mut url = match Url::parse(req.url) {
-            Ok(u) => { u }
-            Err(e) => {
-                return Box::new(futures::future::err(format!("could not parse url: {:?}", e)));
-            }
+impl Resolve for Resolver {
+    type Addrs = GaiAddrs;
+    type Future = futures::future::MapErr<GaiFuture, fn(io::Error) -> io::Error>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        self.0.resolve(name).map_err(dns_error as fn(io::Error) -> io::Error)
+    }
+}
+
+/// Options of the `HyperHurl` connection pool.
+#[derive(Debug, Clone)]
+pub struct HyperOptions {
+    /// Whether to keep connections open and reuse them for the next requests.
+    pub keep_alive: bool,
+
+    /// Duration after which idle connection is closed. If not set, idle connections are kept open.
+    pub idle_timeout: Option<Duration>,
+
+    /// Maximum number of idle connections kept per host.
+    pub max_idle_per_host: usize,
+
+    /// Interval of the TCP keepalive probes. If not set, probes are not sent.
+    pub tcp_keepalive: Option<Duration>,
+
+    /// Maximum duration of establishing connection.
+    ///
+    /// Requests with a different `connect_timeout` are sent through a separate connection pool,
+    /// which is created on the first such request and then kept for the other requests with the same
+    /// timeout.
+    pub connect_timeout: Option<Duration>,
+
+    /// Options of the connections to the `https://` hosts.
+    #[cfg(feature = "tls")]
+    pub tls: TlsOptions
+}
+
+impl Default for HyperOptions {
+    fn default() -> HyperOptions {
+        HyperOptions {
+            keep_alive: true,
+            idle_timeout: Some(Duration::from_secs(IDLE_TIMEOUT)),
+            max_idle_per_host: usize::MAX,
+            tcp_keepalive: None,
+            connect_timeout: Some(Duration::from_secs(CONNECT_TIMEOUT)),
+            #[cfg(feature = "tls")]
+            tls: TlsOptions::default()
+        }
+    }
+}
+
+/// `Hurl` implementation on top of hyper.
+///
+/// It holds a hyper client, so connections are pooled and reused across requests, and a client per
+/// connect timeout overridden by the requests. With the `tls` feature enabled, it connects to the
+/// `https://` hosts too.
+#[derive(Clone)]
+pub struct HyperHurl {
+    client: HyperClient<Connector, Body>,
+    options: HyperOptions,
+    /// Clients of the requests which override the connect timeout, by the timeout.
+    overridden: Arc<Mutex<HashMap<Duration, HyperClient<Connector, Body>>>>
+}
+
+impl HyperHurl {
+    /// Constructs new `HyperHurl` with default options.
+    ///
+    /// Fails when TLS connector could not be constructed.
+    pub fn new() -> Result<HyperHurl, HurlError> {
+        HyperHurl::with_options(HyperOptions::default())
+    }
+
+    /// Constructs new `HyperHurl` with given options.
+    ///
+    /// Fails when TLS options are invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use influent::hurl::hyper::{HyperHurl, HyperOptions};
+    ///
+    /// let hurl = HyperHurl::with_options(HyperOptions {
+    ///     idle_timeout: Some(Duration::from_secs(30)),
+    ///     max_idle_per_host: 8,
+    ///     ..HyperOptions::default()
+    /// }).unwrap();
+    /// ```
+    pub fn with_options(options: HyperOptions) -> Result<HyperHurl, HurlError> {
+        Ok(HyperHurl {
+            client: hyper_client(&options)?,
+            options,
+            overridden: Arc::default()
+        })
+    }
+
+    /// Returns client which connects with the timeout of the request.
+    fn client(&self, connect_timeout: Option<Duration>) -> Result<HyperClient<Connector, Body>, HurlError> {
+        let timeout = match connect_timeout {
+            Some(timeout) if connect_timeout != self.options.connect_timeout => timeout,
+            _ => return Ok(self.client.clone())
         };
 
-        // if request has query
-        if let Some(ref query) = req.query {
-            // if any existing pairs
-            let existing: Vec<(String, String)> = url.query_pairs().map(|(a,b)| (a.to_string(), b.to_string())).collect();
+        let mut overridden = self.overridden.lock().unwrap();
+
+        if let Some(client) = overridden.get(&timeout) {
+            return Ok(client.clone());
+        }
+
+        let client = hyper_client(&HyperOptions { connect_timeout: Some(timeout), ..self.options.clone() })?;
+        overridden.insert(timeout, client.clone());
+
+        Ok(client)
+    }
+}
+
+fn hyper_client(options: &HyperOptions) -> Result<HyperClient<Connector, Body>, HurlError> {
+    let mut connector = HttpConnector::new_with_resolver(Resolver(GaiResolver::new(DNS_THREADS)));
+    connector.set_keepalive(options.tcp_keepalive);
+    connector.set_connect_timeout(options.connect_timeout);
+
+    #[cfg(feature = "tls")]
+    let connector = {
+        connector.enforce_http(false);
+        HttpsConnector::from((connector, options.tls.connector()?))
+    };
+
+    Ok(HyperClient::builder()
+        .keep_alive(options.keep_alive)
+        .keep_alive_timeout(options.idle_timeout)
+        .max_idle_per_host(options.max_idle_per_host)
+        .build(connector))
+}
+
+fn build(req: Request) -> Result<HyperRequest<Body>, HurlError> {
+    // map request method to the hyper's
+    let method = match req.method {
+        Method::POST => HyperMethod::POST,
+        Method::GET  => HyperMethod::GET,
+    };
+
+    let mut url = match Url::parse(req.url) {
+        Ok(u) => { u }
+        Err(e) => {
+            return Err(HurlError::with_source(ErrorKind::InvalidUrl, format!("could not parse {:?}", req.url), e));
+        }
+    };
+
+    // if request has query
+    if let Some(ref query) = req.query {
+        // if any existing pairs
+        let existing: Vec<(String, String)> = url.query_pairs().map(|(a,b)| (a.to_string(), b.to_string())).collect();
+
+        // final pairs
+        let mut pairs: Vec<(&str, &str)> = Vec::new();
+
+        // add first existing
+        for pair in &existing {
+            pairs.push((&pair.0, &pair.1));
+        }
 
-            // final pairs
-            let mut pairs: Vec<(&str, &str)> = Vec::new();
+        // add given query to the pairs
+        for (key, val) in query.iter() {
+            pairs.push((key, val));
+        }
 
-            // add first existing
-            for pair in &existing {
-                pairs.push((&pair.0, &pair.1));
-            }
+        // set new pairs
+        url.query_pairs_mut().clear().extend_pairs(
+            pairs.iter().map(|&(k, v)| { (k, v) })
+        );
+    }
 
-            // add given query to the pairs
-            for (key, val) in query.iter() {
-                pairs.push((key, val));
-            }
+    // create query
+    let mut query = HyperRequest::builder();
+    query.method(method)
+        .uri(url.as_str());
 
-            // set new pairs
-            url.query_pairs_mut().clear().extend_pairs(
-                pairs.iter().map(|&(k, v)| { (&k[..], &v[..]) })
-            );
+    // if request need to be authorized
+    match req.auth {
+        Some(Auth::Basic { username, password }) => {
+            query.header(AUTHORIZATION, format!("Basic {}", base64::encode(format!("{}:{}", username, password))));
+        },
+        Some(Auth::Token(token)) => {
+            query.header(AUTHORIZATION, format!("Token {}", token));
+        },
+        None => {}
+    }
+
+    if let Some(headers) = req.headers {
+        for (name, value) in headers {
+            query.header(name, value);
         }
+    }
+
+    let url = req.url;
+    let body = req.body.unwrap_or_default();
 
-        // create query
-        let mut query = HyperRequest::builder();
-        query.method(method)
-            .uri(url.as_str());
+    query.body(body.into()).map_err(|e| HurlError::with_source(ErrorKind::InvalidUrl, format!("could not build request to {:?}", url), e))
+}
+
+fn headers(map: &HeaderMap) -> HashMap<String, String> {
+    map.iter()
+        .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.as_str().to_string(), value.to_string())))
+        .collect()
+}
+
+fn error(e: HyperError) -> HurlError {
+    let kind = match error::Error::source(&e).and_then(|cause| cause.downcast_ref::<io::Error>()) {
+        Some(cause) if e.is_connect() => connect_error_kind(cause),
+        _ if e.is_connect() || e.is_closed() || e.is_incomplete_message() => ErrorKind::Connect,
+        _ => ErrorKind::Other
+    };
+
+    HurlError::with_source(kind, "could not send request", e)
+}
 
-        // if request need to be authorized
-        if let Some(auth) = req.auth {
-            let auth = base64::encode(&format!("{}:{}", auth.username, auth.password));
-            query.header(AUTHORIZATION, auth);
+fn connect_error_kind(e: &io::Error) -> ErrorKind {
+    #[cfg(feature = "tls")]
+    {
+        if e.get_ref().is_some_and(|inner| inner.is::<::native_tls::Error>()) {
+            return ErrorKind::Tls;
         }
+    }
+
+    match e.kind() {
+        _ if e.get_ref().is_some_and(|inner| inner.is::<DnsError>()) => ErrorKind::Dns,
+        io::ErrorKind::TimedOut => ErrorKind::Timeout,
+        io::ErrorKind::InvalidInput => ErrorKind::InvalidUrl,
+        _ => ErrorKind::Connect
+    }
+}
 
-        let request = if let Some(body) = req.body {
-            query.body(body.into()).unwrap()
-        } else {
-            query.body("".into()).unwrap()
+impl Hurl for HyperHurl {
+    fn request(&self, req: Request) -> HurlResult {
+        let (client, request) = match self.client(req.connect_timeout).and_then(|client| build(req).map(|r| (client, r))) {
+            Ok(r) => r,
+            Err(e) => return Box::new(futures::future::err(e))
         };
 
         Box::new(client
             .request(request)
+            .map_err(error)
             .and_then(|resp| {
                 let status = resp.status().as_u16();
+                let headers = headers(resp.headers());
 
-                resp.into_body().concat2().and_then(move |body| {
-                    Ok(String::from_utf8(body.to_vec()).unwrap())
-                }).and_then(move |body|
-                    Ok(Response {
+                resp.into_body().concat2().map(move |body| {
+                    Response {
                         status,
-                        body
-                    })
-                )
+                        headers,
+                        body: String::from_utf8_lossy(&body).into_owned()
+                    }
+                }).map_err(|e| HurlError::with_source(ErrorKind::Body, "could not read body", e))
             })
-            .map_err(|_| format!(""))
         )
     }
+
+    fn stream(&self, req: Request) -> HurlStreamResult {
+        let (client, request) = match self.client(req.connect_timeout).and_then(|client| build(req).map(|r| (client, r))) {
+            Ok(r) => r,
+            Err(e) => return Box::new(futures::future::err(e))
+        };
+
+        Box::new(client
+            .request(request)
+            .map(|resp| {
+                let status = resp.status().as_u16();
+                let headers = headers(resp.headers());
+                let body = resp.into_body()
+                    .map(|chunk| chunk.to_vec())
+                    .map_err(|e| HurlError::with_source(ErrorKind::Body, "could not read body", e));
+
+                StreamResponse {
+                    status,
+                    headers,
+                    body: Box::new(body)
+                }
+            })
+            .map_err(error)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HyperHurl, HyperOptions, build};
+    use ::hurl::{Hurl, Request, Method, Auth, ErrorKind};
+    use http::header::AUTHORIZATION;
+    use std::error::Error;
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn request<'a>(url: &'a str) -> Request<'a> {
+        Request {
+            url,
+            method: Method::GET,
+            auth: None,
+            query: None,
+            headers: None,
+            body: None,
+            connect_timeout: None
+        }
+    }
+
+    #[test]
+    fn test_auth_header() {
+        let mut req = request("http://localhost:8086/query");
+        req.auth = Some(Auth::Basic { username: "gobwas", password: "1234" });
+        assert_eq!("Basic Z29id2FzOjEyMzQ=", build(req).unwrap().headers()[AUTHORIZATION]);
+
+        let mut req = request("http://localhost:8086/api/v2/query");
+        req.auth = Some(Auth::Token("xxx"));
+        assert_eq!("Token xxx", build(req).unwrap().headers()[AUTHORIZATION]);
+    }
+
+    #[test]
+    fn test_connection_refused() {
+        // bind and drop listener to get port without anyone listening on it
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let url = format!("http://127.0.0.1:{}/ping", port);
+
+        let e = Runtime::new().unwrap().block_on(HyperHurl::new().unwrap().request(request(&url))).unwrap_err();
+
+        assert_eq!(ErrorKind::Connect, e.kind());
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn test_invalid_url() {
+        let e = Runtime::new().unwrap().block_on(HyperHurl::new().unwrap().request(request("not a url"))).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidUrl, e.kind());
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn test_unresolvable_host() {
+        // .invalid is reserved and never resolves
+        let e = Runtime::new().unwrap().block_on(HyperHurl::new().unwrap().request(request("http://nonexistent.invalid/ping"))).unwrap_err();
+
+        assert_eq!(ErrorKind::Dns, e.kind());
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn test_connect_timeout_pools() {
+        let hurl = HyperHurl::with_options(HyperOptions { connect_timeout: Some(Duration::from_secs(10)), ..HyperOptions::default() }).unwrap();
+
+        hurl.client(None).unwrap();
+        hurl.client(Some(Duration::from_secs(10))).unwrap();
+        assert!(hurl.overridden.lock().unwrap().is_empty());
+
+        hurl.client(Some(Duration::from_millis(100))).unwrap();
+        hurl.client(Some(Duration::from_millis(100))).unwrap();
+        hurl.client(Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(2, hurl.overridden.lock().unwrap().len());
+    }
 }
@@ -0,0 +1,76 @@
+use std::mem;
+use futures::{Async, Poll, Stream};
+
+/// Stream adapter, which splits chunks of the underlying stream by the newline.
+///
+/// Yielded lines do not contain trailing `\n`. Trailing bytes not followed by newline are yielded
+/// as the last line.
+pub struct Lines<S> {
+    stream: S,
+    buffer: Vec<u8>,
+    done: bool
+}
+
+impl<S> Lines<S> {
+    /// Constructs new `Lines` over given stream of chunks.
+    pub fn new(stream: S) -> Lines<S> {
+        Lines {
+            stream,
+            buffer: Vec::new(),
+            done: false
+        }
+    }
+}
+
+impl<S> Stream for Lines<S> where S: Stream<Item=Vec<u8>> {
+    type Item = Vec<u8>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Vec<u8>>, S::Error> {
+        loop {
+            if let Some(i) = self.buffer.iter().position(|b| *b == b'\n') {
+                let mut line: Vec<u8> = self.buffer.drain(..=i).collect();
+                line.pop();
+                return Ok(Async::Ready(Some(line)));
+            }
+
+            if self.done {
+                if self.buffer.is_empty() {
+                    return Ok(Async::Ready(None));
+                }
+
+                return Ok(Async::Ready(Some(mem::take(&mut self.buffer))));
+            }
+
+            match self.stream.poll()? {
+                Async::Ready(Some(chunk)) => self.buffer.extend(chunk),
+                Async::Ready(None) => self.done = true,
+                Async::NotReady => return Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lines;
+    use futures::{stream, Future, Stream};
+
+    fn lines(chunks: Vec<&str>) -> Vec<String> {
+        let chunks = chunks.into_iter().map(|c| Ok::<_, ()>(c.as_bytes().to_vec())).collect::<Vec<_>>();
+
+        Lines::new(stream::iter_result(chunks))
+            .map(|line| String::from_utf8(line).unwrap())
+            .collect()
+            .wait()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(vec!["a", "b"], lines(vec!["a\nb\n"]));
+        assert_eq!(vec!["ab", "cd", "e"], lines(vec!["a", "b\nc", "d\n", "e"]));
+        assert_eq!(vec!["", "a"], lines(vec!["\n", "a"]));
+        assert_eq!(Vec::<String>::new(), lines(vec![]));
+    }
+}
@@ -1,10 +1,29 @@
 use std::collections::HashMap;
-use futures::Future;
+use std::error;
+use std::fmt;
+use std::time::Duration;
+use futures::{stream, Future, Stream};
 
 pub mod hyper;
+pub mod lines;
+#[cfg(feature = "tls")]
+pub mod tls;
 
 pub trait Hurl {
-    fn request(&self, Request) -> HurlResult;
+    fn request(&self, req: Request) -> HurlResult;
+
+    /// Sends request, yielding response body as a stream of chunks.
+    ///
+    /// Default implementation yields the whole body of `request` as a single chunk.
+    fn stream(&self, req: Request) -> HurlStreamResult {
+        Box::new(self.request(req).map(|resp| {
+            StreamResponse {
+                status: resp.status,
+                headers: resp.headers,
+                body: Box::new(stream::once(Ok(resp.body.into_bytes())))
+            }
+        }))
+    }
 }
 
 #[derive(Debug)]
@@ -13,31 +32,153 @@ pub struct Request<'a> {
     pub method: Method,
     pub auth: Option<Auth<'a>>,
     pub query: Option<HashMap<&'a str, String>>,
-    pub body: Option<String>
+    /// Additional request headers.
+    pub headers: Option<HashMap<&'a str, String>>,
+    pub body: Option<String>,
+    /// Maximum duration of establishing connection, overriding the one of the `Hurl`.
+    pub connect_timeout: Option<Duration>
 }
 
 #[derive(Debug)]
 pub struct Response {
     pub status: u16,
+    /// Response headers, with lowercase names.
+    pub headers: HashMap<String, String>,
     pub body: String
 }
 
-impl ToString for Response {
-    fn to_string(&self) -> String {
-        self.body.clone()
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.body)
     }
 }
 
-pub type HurlResult = Box<Future<Item=Response, Error=String> + Send>;
+pub struct StreamResponse {
+    pub status: u16,
+    /// Response headers, with lowercase names.
+    pub headers: HashMap<String, String>,
+    pub body: BodyStream
+}
+
+/// Kind of the `HurlError`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    /// Url of the request is invalid.
+    InvalidUrl,
+    /// Host name could not be resolved.
+    Dns,
+    /// Connection could not be established or was broken.
+    Connect,
+    /// TLS handshake failed or TLS is not configured properly.
+    Tls,
+    /// Request did not complete in time.
+    Timeout,
+    /// Response body could not be read.
+    Body,
+    /// Any other error.
+    Other
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            ErrorKind::InvalidUrl => "invalid url",
+            ErrorKind::Dns        => "dns error",
+            ErrorKind::Connect    => "connection error",
+            ErrorKind::Tls        => "tls error",
+            ErrorKind::Timeout    => "timed out",
+            ErrorKind::Body       => "body error",
+            ErrorKind::Other      => "request error"
+        };
 
+        f.write_str(s)
+    }
+}
+
+/// Error of the request, with the error which caused it.
 #[derive(Debug)]
+pub struct HurlError {
+    kind: ErrorKind,
+    message: String,
+    source: Option<Box<dyn error::Error + Send + Sync>>
+}
+
+impl HurlError {
+    /// Constructs new `HurlError`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::hurl::{HurlError, ErrorKind};
+    ///
+    /// let e = HurlError::new(ErrorKind::Timeout, "no response in 1s");
+    ///
+    /// assert_eq!(ErrorKind::Timeout, e.kind());
+    /// assert_eq!("timed out: no response in 1s", e.to_string());
+    /// ```
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> HurlError {
+        HurlError {
+            kind,
+            message: message.into(),
+            source: None
+        }
+    }
+
+    /// Constructs new `HurlError` caused by `source`.
+    pub fn with_source<S, E>(kind: ErrorKind, message: S, source: E) -> HurlError where S: Into<String>, E: Into<Box<dyn error::Error + Send + Sync>> {
+        HurlError {
+            kind,
+            message: message.into(),
+            source: Some(source.into())
+        }
+    }
+
+    /// Returns kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for HurlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)?;
+
+        if let Some(ref source) = self.source {
+            write!(f, ": {}", source)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl error::Error for HurlError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.source {
+            Some(ref source) => Some(&**source),
+            None => None
+        }
+    }
+}
+
+pub type HurlResult = Box<dyn Future<Item=Response, Error=HurlError> + Send>;
+
+pub type BodyStream = Box<dyn Stream<Item=Vec<u8>, Error=HurlError> + Send>;
+
+pub type HurlStreamResult = Box<dyn Future<Item=StreamResponse, Error=HurlError> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Method {
     POST,
     GET
 }
 
 #[derive(Debug)]
-pub struct Auth<'a> {
-    pub username: &'a str,
-    pub password: &'a str
+pub enum Auth<'a> {
+    /// Authentication with username and password of InfluxDB 1.x.
+    Basic {
+        username: &'a str,
+        password: &'a str
+    },
+    /// Authentication with API token of InfluxDB 2.x.
+    Token(&'a str)
 }
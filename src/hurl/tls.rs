@@ -0,0 +1,90 @@
+use ::hurl::{HurlError, ErrorKind};
+use native_tls::{Certificate, Identity, TlsConnector};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Options of the TLS connections.
+///
+/// Server certificates are verified against the system CAs and `ca_certificates`, unless
+/// `skip_verify` is set.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// PEM encoded certificates of the CAs, trusted in addition to the system ones.
+    pub ca_certificates: Vec<Vec<u8>>,
+
+    /// Certificate presented to the servers which require mutual TLS.
+    pub client_certificate: Option<ClientCertificate>,
+
+    /// Whether to accept any server certificate for any host. Should be used only in development.
+    pub skip_verify: bool
+}
+
+/// Client certificate with its private key.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// PEM encoded certificate chain.
+    pub certificate: Vec<u8>,
+
+    /// PEM encoded PKCS #8 private key.
+    pub key: Vec<u8>
+}
+
+impl ClientCertificate {
+    /// Reads certificate chain and private key from the PEM files.
+    pub fn from_files<C: AsRef<Path>, K: AsRef<Path>>(certificate: C, key: K) -> io::Result<ClientCertificate> {
+        Ok(ClientCertificate {
+            certificate: fs::read(certificate)?,
+            key: fs::read(key)?
+        })
+    }
+}
+
+impl TlsOptions {
+    /// Adds CA certificates from the PEM bundle file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use influent::hurl::hyper::{HyperHurl, HyperOptions};
+    /// use influent::hurl::tls::TlsOptions;
+    ///
+    /// let mut tls = TlsOptions::default();
+    /// tls.add_ca_file("/etc/influxdb/ca.pem").unwrap();
+    ///
+    /// let hurl = HyperHurl::with_options(HyperOptions { tls, ..HyperOptions::default() }).unwrap();
+    /// ```
+    pub fn add_ca_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.ca_certificates.push(fs::read(path)?);
+        Ok(())
+    }
+
+    /// Builds TLS connector with these options.
+    pub fn connector(&self) -> Result<TlsConnector, HurlError> {
+        let mut builder = TlsConnector::builder();
+
+        for bundle in &self.ca_certificates {
+            let certificates = Certificate::stack_from_pem(bundle).map_err(|e| HurlError::with_source(ErrorKind::Tls, "could not parse CA certificate", e))?;
+
+            if certificates.is_empty() {
+                return Err(HurlError::new(ErrorKind::Tls, "could not parse CA certificate: no certificates found"));
+            }
+
+            for certificate in certificates {
+                builder.add_root_certificate(certificate);
+            }
+        }
+
+        if let Some(ref client) = self.client_certificate {
+            let identity = Identity::from_pkcs8(&client.certificate, &client.key).map_err(|e| HurlError::with_source(ErrorKind::Tls, "could not parse client certificate", e))?;
+            builder.identity(identity);
+        }
+
+        if self.skip_verify {
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+
+        builder.build().map_err(|e| HurlError::with_source(ErrorKind::Tls, "could not build TLS connector", e))
+    }
+}
@@ -5,21 +5,37 @@ extern crate http;
 extern crate base64;
 extern crate hyper;
 extern crate url;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate rmpv;
+extern crate rand;
+#[cfg(feature = "tls")]
+extern crate hyper_tls;
+#[cfg(feature = "tls")]
+extern crate native_tls;
+
+#[cfg(test)]
+extern crate proptest;
 
 pub mod client;
 pub mod hurl;
 pub mod serializer;
+pub mod parser;
 pub mod measurement;
+pub mod query;
 
 use client::Credentials;
 use client::http::HttpClient;
+use hurl::HurlError;
 use hurl::hyper::HyperHurl;
 use serializer::line::LineSerializer;
 
 /// Simple factory of `HttpClient` with `LineSerializer`
 ///
 /// Takes two parameters, where first is `Credentials` struct, and second - `Vec<&str>`, where each item
-/// is a InfluxDB host url.
+/// is a InfluxDB host url. Fails when TLS connector of `HyperHurl` could not be constructed.
 ///
 /// # Examples
 ///
@@ -33,15 +49,15 @@ use serializer::line::LineSerializer;
 ///     database: "mydb"
 /// };
 ///
-/// let client = create_client(credentials, vec!["http://localhost:8086"]);
+/// let client = create_client(credentials, vec!["http://localhost:8086"]).unwrap();
 /// ```
-pub fn create_client<'a>(credentials: Credentials<'a>, hosts: Vec<&'a str>) -> HttpClient<'a> {
-    let mut client = HttpClient::new(credentials, Box::new(LineSerializer::new()), Box::new(HyperHurl::new()));
+pub fn create_client(credentials: Credentials, hosts: Vec<&str>) -> Result<HttpClient, HurlError> {
+    let mut client = HttpClient::new(credentials, Box::new(LineSerializer::new()), Box::new(HyperHurl::new()?));
 
     for host in hosts {
         client.add_host(host);
     }
 
-    client
+    Ok(client)
 }
 
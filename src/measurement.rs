@@ -1,23 +1,55 @@
 use std::collections::BTreeMap;
 use std::borrow::Cow;
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 /// Measurement's field value.
 pub enum Value<'a> {
     /// String.
-    String(&'a str),
+    String(Cow<'a, str>),
     /// Floating point number.
     Float(f64),
     /// Integer number.
     Integer(i64),
+    /// Unsigned integer number. Supported since InfluxDB 1.4.
+    UInteger(u64),
     /// Boolean value.
     Boolean(bool)
 }
 
+impl<'a> Value<'a> {
+    /// Converts value into the one which owns its data.
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::String(s) => Value::String(Cow::Owned(s.into_owned())),
+            Value::Float(f) => Value::Float(f),
+            Value::Integer(i) => Value::Integer(i),
+            Value::UInteger(u) => Value::UInteger(u),
+            Value::Boolean(b) => Value::Boolean(b)
+        }
+    }
+
+    /// Returns value which borrows data of this one.
+    pub fn to_borrowed(&self) -> Value<'_> {
+        match *self {
+            Value::String(ref s) => Value::String(Cow::Borrowed(s)),
+            Value::Float(f) => Value::Float(f),
+            Value::Integer(i) => Value::Integer(i),
+            Value::UInteger(u) => Value::UInteger(u),
+            Value::Boolean(b) => Value::Boolean(b)
+        }
+    }
+}
+
+/// Measurement which owns its data, so it could be sent to other threads and kept for any time.
+pub type OwnedMeasurement = Measurement<'static>;
+
 /// Measurement model.
-#[derive(Debug)]
+///
+/// Key, tags, field names and string values are either borrowed or owned, so measurement could
+/// be built from borrowed data without copying, or converted with `into_owned` to be kept further.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Measurement<'a> {
     /// Key.
-    pub key: &'a str,
+    pub key: Cow<'a, str>,
 
     /// Timestamp.
     pub timestamp: Option<i64>,
@@ -38,9 +70,9 @@ impl<'a> Measurement<'a> {
     ///
     /// let measurement = Measurement::new("key");
     /// ```
-    pub fn new(key: &str) -> Measurement {
+    pub fn new<K>(key: K) -> Measurement<'a> where K: Into<Cow<'a, str>> {
         Measurement {
-            key: key,
+            key: key.into(),
             timestamp: None,
             fields: BTreeMap::new(),
             tags: BTreeMap::new()
@@ -56,7 +88,7 @@ impl<'a> Measurement<'a> {
     ///
     /// let mut measurement = Measurement::new("key");
     ///
-    /// measurement.add_field("field", Value::String("hello"));
+    /// measurement.add_field("field", Value::String("hello".into()));
     /// ```
     pub fn add_field<T>(&mut self, field: T, value: Value<'a>) where T: Into<Cow<'a, str>> {
         self.fields.insert(field.into(), value);
@@ -91,4 +123,52 @@ impl<'a> Measurement<'a> {
     pub fn set_timestamp(&mut self, timestamp: i64) {
         self.timestamp = Some(timestamp);
     }
+
+    /// Converts measurement into the one which owns its data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use influent::measurement::{Measurement, OwnedMeasurement, Value};
+    ///
+    /// let key = String::from("key");
+    /// let mut measurement = Measurement::new(key.as_str());
+    ///
+    /// measurement.add_field("field", Value::String("hello".into()));
+    ///
+    /// let owned: OwnedMeasurement = measurement.into_owned();
+    /// drop(key);
+    ///
+    /// thread::spawn(move || assert_eq!("key", owned.key)).join().unwrap();
+    /// ```
+    pub fn into_owned(self) -> Measurement<'static> {
+        Measurement {
+            key: Cow::Owned(self.key.into_owned()),
+            timestamp: self.timestamp,
+            fields: self.fields.into_iter().map(|(k, v)| (Cow::Owned(k.into_owned()), v.into_owned())).collect(),
+            tags: self.tags.into_iter().map(|(k, v)| (Cow::Owned(k.into_owned()), Cow::Owned(v.into_owned()))).collect()
+        }
+    }
+
+    /// Returns measurement which borrows data of this one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::measurement::{Measurement, OwnedMeasurement, Value};
+    ///
+    /// let mut owned: OwnedMeasurement = Measurement::new(String::from("key"));
+    /// owned.add_field(String::from("field"), Value::Integer(1));
+    ///
+    /// assert_eq!(owned, owned.to_borrowed());
+    /// ```
+    pub fn to_borrowed(&self) -> Measurement<'_> {
+        Measurement {
+            key: Cow::Borrowed(&self.key),
+            timestamp: self.timestamp,
+            fields: self.fields.iter().map(|(k, v)| (Cow::Borrowed(k.as_ref()), v.to_borrowed())).collect(),
+            tags: self.tags.iter().map(|(k, v)| (Cow::Borrowed(k.as_ref()), Cow::Borrowed(v.as_ref()))).collect()
+        }
+    }
 }
@@ -0,0 +1,414 @@
+use ::measurement::{Measurement, Value};
+use ::parser::{Parser, ParseError};
+use std::borrow::Cow;
+
+/// Characters which end and could be escaped in the measurement name.
+const NAME: &[u8] = b", ";
+
+/// Characters which end and could be escaped in the tag keys, tag values and field keys.
+const KEY: &[u8] = b",= ";
+
+#[derive(Default)]
+pub struct LineParser;
+
+/// Line protocol `Measurement` parser.
+impl LineParser {
+    /// Constructs new `LineParser`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::parser::Parser;
+    /// use influent::parser::line::LineParser;
+    /// use influent::measurement::Value;
+    ///
+    /// let parser = LineParser::new();
+    /// let measurements = parser.parse("key,tag=value field=\"value\",n=1i 10").unwrap();
+    ///
+    /// assert_eq!("key", measurements[0].key);
+    /// assert_eq!("value", measurements[0].tags["tag"]);
+    /// assert_eq!(Value::Integer(1), measurements[0].fields["n"]);
+    /// assert_eq!(Some(10), measurements[0].timestamp);
+    /// ```
+    pub fn new() -> LineParser {
+        LineParser
+    }
+}
+
+impl Parser for LineParser {
+    fn parse<'a>(&self, input: &'a str) -> Result<Vec<Measurement<'a>>, ParseError> {
+        let mut scanner = Scanner { input, pos: 0 };
+        let mut measurements = Vec::new();
+
+        while let Some(b) = scanner.peek() {
+            match b {
+                b'\n' | b'\r' | b' ' | b'\t' => scanner.pos += 1,
+                b'#' => scanner.skip_line(),
+                _ => measurements.push(scanner.line()?)
+            }
+        }
+
+        Ok(measurements)
+    }
+}
+
+struct Scanner<'a> {
+    input: &'a str,
+    pos: usize
+}
+
+impl<'a> Scanner<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).cloned()
+    }
+
+    fn is_end_of_line(&self) -> bool {
+        match self.peek() {
+            None | Some(b'\n') => true,
+            Some(b'\r') => self.input.as_bytes().get(self.pos + 1) == Some(&b'\n'),
+            _ => false
+        }
+    }
+
+    fn error<T, S: Into<String>>(&self, pos: usize, message: S) -> Result<T, ParseError> {
+        let before = &self.input[..pos];
+        let start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        Err(ParseError {
+            line: before.matches('\n').count() + 1,
+            column: before[start..].chars().count() + 1,
+            message: message.into()
+        })
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+
+            if b == b'\n' {
+                break;
+            }
+        }
+    }
+
+    fn line(&mut self) -> Result<Measurement<'a>, ParseError> {
+        let start = self.pos;
+        let key = self.identifier(NAME);
+
+        if key.is_empty() {
+            return self.error(start, "missing measurement name");
+        }
+
+        let mut measurement = Measurement::new(key);
+
+        while self.peek() == Some(b',') {
+            self.pos += 1;
+
+            let tag = self.key("tag key")?;
+            let start = self.pos;
+            let value = self.identifier(KEY);
+
+            if value.is_empty() {
+                return self.error(start, "missing tag value");
+            }
+
+            measurement.add_tag(tag, value);
+        }
+
+        if self.peek() != Some(b' ') {
+            return self.error(self.pos, "missing fields");
+        }
+
+        loop {
+            self.pos += 1;
+
+            let field = self.key("field key")?;
+            let value = self.value()?;
+
+            measurement.add_field(field, value);
+
+            if self.peek() != Some(b',') {
+                break;
+            }
+        }
+
+        if self.peek() == Some(b' ') {
+            self.pos += 1;
+
+            let start = self.pos;
+            let token = self.token();
+
+            match token.parse() {
+                Ok(timestamp) => measurement.set_timestamp(timestamp),
+                Err(_) => return self.error(start, format!("invalid timestamp: {:?}", token))
+            }
+        }
+
+        if !self.is_end_of_line() {
+            return self.error(self.pos, "unexpected character after measurement");
+        }
+
+        self.skip_line();
+
+        Ok(measurement)
+    }
+
+    /// Reads non-empty key, followed by `=`.
+    fn key(&mut self, what: &str) -> Result<Cow<'a, str>, ParseError> {
+        let start = self.pos;
+        let key = self.identifier(KEY);
+
+        if key.is_empty() {
+            return self.error(start, format!("missing {}", what));
+        }
+
+        if self.peek() != Some(b'=') {
+            return self.error(self.pos, format!("missing `=` after {}", what));
+        }
+
+        self.pos += 1;
+
+        Ok(key)
+    }
+
+    /// Reads unquoted identifier, up to the first unescaped character of `special`.
+    ///
+    /// Backslash escapes characters of `special` and backslash, otherwise it is taken literally.
+    fn identifier(&mut self, special: &[u8]) -> Cow<'a, str> {
+        let input = self.input;
+        let bytes = input.as_bytes();
+
+        let start = self.pos;
+        let mut chunk = start;
+        let mut unescaped: Option<String> = None;
+
+        while let Some(&b) = bytes.get(self.pos) {
+            if b == b'\\' && bytes.get(self.pos + 1).is_some_and(|&c| c == b'\\' || special.contains(&c)) {
+                unescaped.get_or_insert_with(String::new).push_str(&input[chunk..self.pos]);
+                chunk = self.pos + 1;
+                self.pos += 2;
+                continue;
+            }
+
+            if b == b'\n' || special.contains(&b) {
+                break;
+            }
+
+            self.pos += 1;
+        }
+
+        match unescaped {
+            Some(mut s) => {
+                s.push_str(&input[chunk..self.pos]);
+                Cow::Owned(s)
+            },
+            None => Cow::Borrowed(&input[start..self.pos])
+        }
+    }
+
+    /// Reads unquoted token, up to the space, comma or end of line.
+    fn token(&mut self) -> &'a str {
+        let start = self.pos;
+
+        while !self.is_end_of_line() && self.peek() != Some(b' ') && self.peek() != Some(b',') {
+            self.pos += 1;
+        }
+
+        &self.input[start..self.pos]
+    }
+
+    fn value(&mut self) -> Result<Value<'a>, ParseError> {
+        let start = self.pos;
+
+        if self.peek() == Some(b'"') {
+            return self.string().map(Value::String);
+        }
+
+        let token = self.token();
+
+        if token.is_empty() {
+            return self.error(start, "missing field value");
+        }
+
+        match parse_value(token) {
+            Ok(value) => Ok(value),
+            Err(message) => self.error(start, message)
+        }
+    }
+
+    /// Reads quoted string, where backslash escapes quotes and backslashes.
+    fn string(&mut self) -> Result<Cow<'a, str>, ParseError> {
+        let input = self.input;
+        let bytes = input.as_bytes();
+
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut chunk = self.pos;
+        let mut unescaped: Option<String> = None;
+
+        while let Some(&b) = bytes.get(self.pos) {
+            match b {
+                b'\\' if bytes.get(self.pos + 1).is_some_and(|&c| c == b'"' || c == b'\\') => {
+                    unescaped.get_or_insert_with(String::new).push_str(&input[chunk..self.pos]);
+                    chunk = self.pos + 1;
+                    self.pos += 2;
+                },
+                b'"' => {
+                    let end = self.pos;
+                    self.pos += 1;
+
+                    return Ok(match unescaped {
+                        Some(mut s) => {
+                            s.push_str(&input[chunk..end]);
+                            Cow::Owned(s)
+                        },
+                        None => Cow::Borrowed(&input[chunk..end])
+                    });
+                },
+                _ => self.pos += 1
+            }
+        }
+
+        self.error(start, "unterminated string")
+    }
+}
+
+fn parse_value(token: &str) -> Result<Value<'static>, String> {
+    match token {
+        "t" | "T" | "true" | "True" | "TRUE" => return Ok(Value::Boolean(true)),
+        "f" | "F" | "false" | "False" | "FALSE" => return Ok(Value::Boolean(false)),
+        _ => {}
+    }
+
+    if let Some(number) = token.strip_suffix('i') {
+        return number.parse().map(Value::Integer).map_err(|_| format!("invalid integer: {:?}", token));
+    }
+
+    if let Some(number) = token.strip_suffix('u') {
+        return number.parse().map(Value::UInteger).map_err(|_| format!("invalid unsigned integer: {:?}", token));
+    }
+
+    match token.as_bytes()[0] {
+        b'0'..=b'9' | b'-' | b'+' | b'.' => match token.parse::<f64>() {
+            Ok(f) if f.is_finite() => Ok(Value::Float(f)),
+            _ => Err(format!("invalid float: {:?}", token))
+        },
+        _ => Err(format!("invalid field value: {:?}", token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineParser;
+    use ::parser::{Parser, ParseError};
+    use ::measurement::{Measurement, Value};
+    use std::borrow::Cow;
+
+    fn parse(input: &str) -> Vec<Measurement<'_>> {
+        LineParser::new().parse(input).unwrap()
+    }
+
+    fn error(input: &str) -> (usize, usize) {
+        let ParseError { line, column, .. } = LineParser::new().parse(input).unwrap_err();
+        (line, column)
+    }
+
+    #[test]
+    fn test_parse() {
+        let measurements = parse("cpu,host=a,region=west usage=0.5,count=10i,up=t,name=\"x\" 1434055562000000000\nmem free=1");
+
+        assert_eq!(2, measurements.len());
+
+        let mut cpu = Measurement::new("cpu");
+        cpu.add_tag("host", "a");
+        cpu.add_tag("region", "west");
+        cpu.add_field("usage", Value::Float(0.5));
+        cpu.add_field("count", Value::Integer(10));
+        cpu.add_field("up", Value::Boolean(true));
+        cpu.add_field("name", Value::String("x".into()));
+        cpu.set_timestamp(1434055562000000000);
+
+        let mut mem = Measurement::new("mem");
+        mem.add_field("free", Value::Float(1f64));
+
+        assert_eq!(cpu, measurements[0]);
+        assert_eq!(mem, measurements[1]);
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let measurements = parse("my\\ key\\,x,t\\=k=v\\ \\,a f\\=1=\"say \\\"hi\\\" \\\\ \\n\",b\\a=1i");
+        let m = &measurements[0];
+
+        assert_eq!("my key,x", m.key);
+        assert_eq!("v ,a", m.tags["t=k"]);
+        assert_eq!(Value::String("say \"hi\" \\ \\n".into()), m.fields["f=1"]);
+        assert_eq!(Value::Integer(1), m.fields["b\\a"]);
+    }
+
+    #[test]
+    fn test_parse_borrows() {
+        let measurements = parse("key,tag=value field=\"value\"");
+        let m = &measurements[0];
+
+        assert!(matches!(m.key, Cow::Borrowed(_)));
+        assert!(matches!(m.fields["field"], Value::String(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let m = &parse("key a=-1i,b=18446744073709551615u,c=1e3,d=-.5,e=F,f=false,g=TRUE,h=\"multi\nline\"")[0];
+
+        assert_eq!(Value::Integer(-1), m.fields["a"]);
+        assert_eq!(Value::UInteger(u64::MAX), m.fields["b"]);
+        assert_eq!(Value::Float(1000f64), m.fields["c"]);
+        assert_eq!(Value::Float(-0.5), m.fields["d"]);
+        assert_eq!(Value::Boolean(false), m.fields["e"]);
+        assert_eq!(Value::Boolean(false), m.fields["f"]);
+        assert_eq!(Value::Boolean(true), m.fields["g"]);
+        assert_eq!(Value::String("multi\nline".into()), m.fields["h"]);
+    }
+
+    #[test]
+    fn test_parse_skips_comments_and_blank_lines() {
+        let measurements = parse("# comment\n\n  a x=1i\r\n\n# b x=1i\nc x=2i 5\n");
+
+        assert_eq!(2, measurements.len());
+        assert_eq!("a", measurements[0].key);
+        assert_eq!("c", measurements[1].key);
+        assert_eq!(Some(5), measurements[1].timestamp);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!((1, 4), error("key"));
+        assert_eq!((1, 1), error(",tag=a x=1"));
+        assert_eq!((1, 9), error("key,tag= x=1"));
+        assert_eq!((1, 6), error("key x"));
+        assert_eq!((1, 7), error("key x=abc"));
+        assert_eq!((1, 7), error("key x=1.5i"));
+        assert_eq!((1, 7), error("key x=18446744073709551616u"));
+        assert_eq!((1, 7), error("key x=-1u"));
+        assert_eq!((1, 7), error("key x=\"abc"));
+        assert_eq!((1, 9), error("key x=1 now"));
+        assert_eq!((1, 11), error("key x=1 10 20"));
+        assert_eq!((2, 7), error("a x=1\nкей x=NaN"));
+    }
+
+    #[test]
+    fn test_parse_non_ascii_values() {
+        assert_eq!(Value::String("é".into()), parse("key x=\"é\"")[0].fields["x"]);
+        assert_eq!((1, 7), error("key x=é"));
+        assert_eq!((1, 7), error("key x=1é"));
+        assert_eq!((1, 7), error("key x=éi"));
+        assert_eq!((1, 11), error("key x=1,y=€"));
+    }
+
+    #[test]
+    fn test_parse_error_message() {
+        let e = LineParser::new().parse("key x=yes").unwrap_err();
+
+        assert_eq!("invalid field value: \"yes\" at line 1, column 7", e.to_string());
+    }
+}
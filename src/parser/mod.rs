@@ -0,0 +1,32 @@
+use ::measurement::Measurement;
+use std::error;
+use std::fmt;
+
+pub mod line;
+
+/// `Measurement` parser.
+pub trait Parser {
+    /// Parses measurements from string.
+    fn parse<'a>(&self, input: &'a str) -> Result<Vec<Measurement<'a>>, ParseError>;
+}
+
+/// Error of parsing measurements.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Line of the input where error occurred, starting from 1.
+    pub line: usize,
+
+    /// Column of the line where error occurred, in characters, starting from 1.
+    pub column: usize,
+
+    /// Description of the error.
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl error::Error for ParseError {}
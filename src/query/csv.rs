@@ -0,0 +1,703 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map, Number};
+use ::parser::ParseError;
+use super::{QueryResponse, StatementResult, Series, Value};
+
+/// Data type of the column, given by the `#datatype` annotation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    String,
+    Double,
+    Long,
+    UnsignedLong,
+    Boolean,
+    /// RFC3339 formatted time, kept as string.
+    DateTime,
+    /// Duration, kept as string.
+    Duration,
+    /// Base64 encoded binary, kept as string.
+    Base64Binary
+}
+
+impl FromStr for DataType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DataType, String> {
+        match s {
+            "string"       => Ok(DataType::String),
+            "double"       => Ok(DataType::Double),
+            "long"         => Ok(DataType::Long),
+            "unsignedLong" => Ok(DataType::UnsignedLong),
+            "boolean"      => Ok(DataType::Boolean),
+            "duration"     => Ok(DataType::Duration),
+            "base64Binary" => Ok(DataType::Base64Binary),
+            _ if s.starts_with("dateTime") => Ok(DataType::DateTime),
+            _              => Err(format!("unknown data type: {}", s))
+        }
+    }
+}
+
+/// Column of the table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+
+    /// Type of the values. Columns without `#datatype` annotation are strings.
+    pub data_type: DataType,
+
+    /// Whether column is a part of the group key, given by the `#group` annotation.
+    pub group: bool,
+
+    /// Value used for the empty fields, given by the `#default` annotation.
+    pub default: Option<String>
+}
+
+/// Table of the annotated CSV response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub columns: Vec<Column>,
+
+    /// Records of typed values, ordered as `columns`. Empty fields without default are nulls.
+    pub records: Vec<Vec<Value>>
+}
+
+impl Table {
+    /// Returns index of the column with given name.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
+    }
+
+    /// Returns error message, if table reports an error of the query.
+    pub fn error(&self) -> Option<&str> {
+        let index = match self.columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>()[..] {
+            ["error", "reference"] | ["error"] => 0,
+            _ => return None
+        };
+
+        self.records.first().and_then(|record| record[index].as_str())
+    }
+
+    /// Deserializes each record of the table into `T`, mapping values by column name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate influent;
+    /// #[macro_use]
+    /// extern crate serde_derive;
+    ///
+    /// use influent::query::csv::parse_annotated;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Row {
+    ///     _time: String,
+    ///     _value: f64,
+    ///     host: String
+    /// }
+    ///
+    /// # fn main() {
+    /// let body = "#datatype,string,long,dateTime:RFC3339,double,string\n\
+    ///             #group,false,false,false,false,true\n\
+    ///             #default,_result,,,,\n\
+    ///             ,result,table,_time,_value,host\n\
+    ///             ,,0,2020-01-01T00:00:00Z,0.5,a\n";
+    ///
+    /// let tables = parse_annotated(body).unwrap();
+    /// let rows: Vec<Row> = tables[0].rows().unwrap();
+    ///
+    /// assert_eq!("2020-01-01T00:00:00Z", rows[0]._time);
+    /// assert_eq!(0.5, rows[0]._value);
+    /// assert_eq!("a", rows[0].host);
+    /// # }
+    /// ```
+    pub fn rows<T>(&self) -> Result<Vec<T>, serde_json::Error> where T: DeserializeOwned {
+        self.records.iter().map(|record| {
+            let row: Map<String, Value> = self.columns.iter()
+                .zip(record)
+                .map(|(column, value)| (column.name.clone(), value.clone()))
+                .collect();
+
+            serde_json::from_value(Value::Object(row))
+        }).collect()
+    }
+}
+
+/// Parses annotated CSV, returned for the Flux queries.
+///
+/// Tables are separated by the blank lines or by the change of the `result` and `table` columns.
+/// Rows starting with `#` are annotations of the following table header.
+///
+/// # Examples
+///
+/// ```
+/// use influent::query::csv::{parse_annotated, DataType};
+/// use influent::query::Value;
+///
+/// let body = "#datatype,string,long,double\n\
+///             #group,false,false,false\n\
+///             #default,_result,,\n\
+///             ,result,table,_value\n\
+///             ,,0,1.5\n\
+///             ,,1,\n";
+///
+/// let tables = parse_annotated(body).unwrap();
+///
+/// assert_eq!(2, tables.len());
+/// assert_eq!(DataType::Double, tables[0].columns[2].data_type);
+/// assert_eq!(Value::from(1.5), tables[0].records[0][2]);
+/// assert_eq!(Value::from("_result"), tables[1].records[0][0]);
+/// assert_eq!(Value::Null, tables[1].records[0][2]);
+/// ```
+pub fn parse_annotated(input: &str) -> Result<Vec<Table>, ParseError> {
+    let mut reader = Reader { input, pos: 0 };
+    let mut tables = Vec::new();
+
+    let mut annotations = Annotations::default();
+    let mut header: Option<(usize, Vec<Column>)> = None;
+    let mut current: Option<(Vec<String>, Table)> = None;
+
+    loop {
+        let start = reader.pos;
+
+        let record = match reader.record() {
+            Some(record) => record?,
+            None => break
+        };
+
+        // blank line ends the block of tables with the same header
+        if record.is_empty() {
+            annotations = Annotations::default();
+            header = None;
+            tables.extend(current.take().map(|(_, table)| table));
+            continue;
+        }
+
+        if record[0].starts_with('#') {
+            if header.is_some() {
+                header = None;
+                tables.extend(current.take().map(|(_, table)| table));
+            }
+
+            annotations.add(record).map_err(|message| reader.error(start, message))?;
+            continue;
+        }
+
+        let (skip, columns) = match header {
+            Some(ref header) => (header.0, &header.1),
+            None => {
+                header = Some(annotations.columns(record).map_err(|message| reader.error(start, message))?);
+                annotations = Annotations::default();
+                continue;
+            }
+        };
+
+        if record.len() != skip + columns.len() {
+            return Err(reader.error(start, format!("expected {} fields, found {}", skip + columns.len(), record.len())));
+        }
+
+        let values = record[skip..].iter()
+            .zip(columns)
+            .map(|(field, column)| value(field, column))
+            .collect::<Result<Vec<Value>, String>>()
+            .map_err(|message| reader.error(start, message))?;
+
+        let key: Vec<String> = columns.iter()
+            .zip(&record[skip..])
+            .filter(|&(column, _)| column.name == "result" || column.name == "table")
+            .map(|(_, field)| field.clone())
+            .collect();
+
+        match current {
+            Some((ref current_key, ref mut table)) if *current_key == key => table.records.push(values),
+            _ => {
+                tables.extend(current.take().map(|(_, table)| table));
+                current = Some((key, Table { columns: columns.clone(), records: vec![values] }));
+            }
+        }
+    }
+
+    tables.extend(current.map(|(_, table)| table));
+
+    Ok(tables)
+}
+
+/// Parses CSV response of the InfluxDB 1.x `/query` endpoint.
+///
+/// Values are not typed in this format, so integers, floats and booleans are recognized by their
+/// form, and the other values are strings. Statements are separated by the blank lines. Server
+/// omits statements without series, so `statement_id` is the index among the returned statements.
+///
+/// # Examples
+///
+/// ```
+/// use influent::query::csv::parse_query;
+/// use influent::query::Value;
+///
+/// let body = "name,tags,time,value\n\
+///             cpu,\"host=a,region=eu\",1434055562000000000,0.5\n";
+///
+/// let response = parse_query(body).unwrap();
+/// let series = &response.results[0].series[0];
+///
+/// assert_eq!("cpu", series.name);
+/// assert_eq!("eu", series.tags["region"]);
+/// assert_eq!(vec!["time", "value"], series.columns);
+/// assert_eq!(vec![Value::from(1434055562000000000i64), Value::from(0.5)], series.values[0]);
+/// ```
+pub fn parse_query(input: &str) -> Result<QueryResponse, ParseError> {
+    let mut reader = Reader { input, pos: 0 };
+    let mut response = QueryResponse { results: Vec::new(), error: None };
+
+    let mut header: Option<Vec<String>> = None;
+    let mut result: Option<StatementResult> = None;
+
+    loop {
+        let start = reader.pos;
+
+        let record = match reader.record() {
+            Some(record) => record?,
+            None => break
+        };
+
+        if record.is_empty() {
+            header = None;
+            response.results.extend(result.take());
+            continue;
+        }
+
+        // header is written for each statement and whenever columns change
+        if record == ["error"] || record.len() >= 2 && record[0] == "name" && record[1] == "tags" {
+            header = Some(record);
+            continue;
+        }
+
+        let columns = match header {
+            Some(ref header) => &header[..],
+            None => return Err(reader.error(start, "expected header"))
+        };
+
+        if record.len() != columns.len() {
+            return Err(reader.error(start, format!("expected {} fields, found {}", columns.len(), record.len())));
+        }
+
+        let statement_id = response.results.len() as u32;
+        let result = result.get_or_insert_with(|| StatementResult {
+            statement_id,
+            series: Vec::new(),
+            messages: Vec::new(),
+            error: None,
+            partial: false
+        });
+
+        if columns == ["error"] {
+            result.error = record.into_iter().next();
+            continue;
+        }
+
+        let mut fields = record.into_iter();
+        let name = fields.next().unwrap_or_default();
+        let tags = tags(&fields.next().unwrap_or_default()).map_err(|message| reader.error(start, message))?;
+        let values: Vec<Value> = fields.map(|field| infer(&field)).collect();
+
+        match result.series.last_mut() {
+            Some(ref mut series) if series.name == name && series.tags == tags && series.columns[..] == columns[2..] => {
+                series.values.push(values);
+                continue;
+            },
+            _ => {}
+        }
+
+        result.series.push(Series {
+            name,
+            tags,
+            columns: columns[2..].to_vec(),
+            values: vec![values],
+            partial: false
+        });
+    }
+
+    response.results.extend(result);
+
+    Ok(response)
+}
+
+/// Parses tags of the series, formatted as `key=value` pairs separated by commas, with commas,
+/// equal signs and spaces escaped by backslash.
+fn tags(s: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut tags = BTreeMap::new();
+
+    if s.is_empty() {
+        return Ok(tags);
+    }
+
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => chars.next().unwrap_or(c),
+            '=' if !in_value => {
+                in_value = true;
+                continue;
+            },
+            ',' => {
+                if !in_value {
+                    return Err(format!("invalid tags: {}", s));
+                }
+
+                tags.insert(key.split_off(0), value.split_off(0));
+                in_value = false;
+                continue;
+            },
+            c => c
+        };
+
+        if in_value { value.push(c) } else { key.push(c) }
+    }
+
+    if !in_value {
+        return Err(format!("invalid tags: {}", s));
+    }
+
+    tags.insert(key, value);
+
+    Ok(tags)
+}
+
+/// Recognizes type of the untyped value.
+fn infer(field: &str) -> Value {
+    if field.is_empty() {
+        return Value::Null;
+    }
+
+    if let Ok(n) = field.parse::<i64>() {
+        return Value::from(n);
+    }
+
+    if let Ok(n) = field.parse::<u64>() {
+        return Value::from(n);
+    }
+
+    match field {
+        "true" => return Value::Bool(true),
+        "false" => return Value::Bool(false),
+        _ => {}
+    }
+
+    // floats are written without exponent, so strings like "inf" or "1e3" are not taken as numbers
+    if field.bytes().all(|b| b.is_ascii_digit() || b == b'.' || b == b'-') {
+        if let Some(n) = field.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Value::Number(n);
+        }
+    }
+
+    Value::String(field.to_string())
+}
+
+/// Annotations of the following table header.
+#[derive(Default)]
+struct Annotations {
+    datatype: Option<Vec<String>>,
+    group: Option<Vec<String>>,
+    default: Option<Vec<String>>
+}
+
+impl Annotations {
+    fn add(&mut self, mut record: Vec<String>) -> Result<(), String> {
+        let name = record.remove(0);
+
+        match name.as_str() {
+            "#datatype" => self.datatype = Some(record),
+            "#group" => self.group = Some(record),
+            "#default" => self.default = Some(record),
+            _ => return Err(format!("unknown annotation: {}", name))
+        }
+
+        Ok(())
+    }
+
+    /// Builds columns of the header, returning also the number of leading annotation fields.
+    fn columns(&self, record: Vec<String>) -> Result<(usize, Vec<Column>), String> {
+        // annotated tables have empty first field, where rows of other tables have annotation names
+        let skip = if record[0].is_empty() { 1 } else { 0 };
+        let names = &record[skip..];
+
+        for annotation in [&self.datatype, &self.group, &self.default].iter().filter_map(|a| a.as_ref()) {
+            if annotation.len() != names.len() {
+                return Err(format!("expected {} annotations, found {}", names.len(), annotation.len()));
+            }
+        }
+
+        let columns = names.iter().enumerate().map(|(i, name)| {
+            Ok(Column {
+                name: name.clone(),
+                data_type: match self.datatype {
+                    Some(ref types) => types[i].parse()?,
+                    None => DataType::String
+                },
+                group: self.group.as_ref().is_some_and(|group| group[i] == "true"),
+                default: self.default.as_ref().map(|default| default[i].clone()).filter(|default| !default.is_empty())
+            })
+        }).collect::<Result<Vec<Column>, String>>()?;
+
+        Ok((skip, columns))
+    }
+}
+
+fn value(field: &str, column: &Column) -> Result<Value, String> {
+    let field = match (field, column.default.as_ref()) {
+        ("", Some(default)) => default.as_str(),
+        ("", None) => return Ok(Value::Null),
+        (field, _) => field
+    };
+
+    let invalid = |e: &dyn ToString| format!("invalid {:?} value of column {:?}: {}", field, column.name, e.to_string());
+
+    match column.data_type {
+        DataType::Double => {
+            let f = field.parse::<f64>().map_err(|e| invalid(&e))?;
+
+            // JSON numbers could not be NaN or infinite, so such values are strings, as InfluxDB
+            // writes them in CSV responses
+            Ok(Number::from_f64(f).map(Value::Number).unwrap_or_else(|| Value::String(field.to_string())))
+        },
+        DataType::Long => field.parse::<i64>().map(Value::from).map_err(|e| invalid(&e)),
+        DataType::UnsignedLong => field.parse::<u64>().map(Value::from).map_err(|e| invalid(&e)),
+        DataType::Boolean => match field {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(invalid(&"expected true or false"))
+        },
+        DataType::String | DataType::DateTime | DataType::Duration | DataType::Base64Binary => Ok(Value::String(field.to_string()))
+    }
+}
+
+/// Reader of the RFC 4180 CSV records.
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    /// Reads next record. Blank line is read as an empty record.
+    fn record(&mut self) -> Option<Result<Vec<String>, ParseError>> {
+        if self.pos >= self.input.len() {
+            return None;
+        }
+
+        if self.newline() {
+            return Some(Ok(Vec::new()));
+        }
+
+        let mut fields = Vec::new();
+
+        loop {
+            let field = match self.field() {
+                Ok(field) => field,
+                Err(e) => return Some(Err(e))
+            };
+
+            fields.push(field);
+
+            if self.newline() || self.pos >= self.input.len() {
+                return Some(Ok(fields));
+            }
+
+            if self.input.as_bytes()[self.pos] != b',' {
+                return Some(Err(self.error(self.pos, "expected delimiter after quoted field")));
+            }
+
+            self.pos += 1;
+        }
+    }
+
+    fn field(&mut self) -> Result<String, ParseError> {
+        let rest = &self.input[self.pos..];
+
+        if !rest.starts_with('"') {
+            let end = rest.find([',', '\r', '\n']).unwrap_or(rest.len());
+            self.pos += end;
+
+            return Ok(rest[..end].to_string());
+        }
+
+        let start = self.pos;
+        let mut field = String::new();
+        self.pos += 1;
+
+        loop {
+            let rest = &self.input[self.pos..];
+            let end = match rest.find('"') {
+                Some(end) => end,
+                None => return Err(self.error(start, "unterminated quoted field"))
+            };
+
+            field.push_str(&rest[..end]);
+            self.pos += end + 1;
+
+            // doubled quote is an escaped quote
+            if self.input[self.pos..].starts_with('"') {
+                field.push('"');
+                self.pos += 1;
+            } else {
+                return Ok(field);
+            }
+        }
+    }
+
+    /// Consumes line ending, if it is at the current position.
+    fn newline(&mut self) -> bool {
+        let rest = &self.input[self.pos..];
+
+        if rest.starts_with("\r\n") {
+            self.pos += 2;
+        } else if rest.starts_with('\n') {
+            self.pos += 1;
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    fn error<S: Into<String>>(&self, pos: usize, message: S) -> ParseError {
+        let before = &self.input[..pos];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        ParseError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: message.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_annotated, parse_query, DataType};
+    use ::query::Value;
+
+    const BODY: &str = "#datatype,string,long,dateTime:RFC3339,double,string\r\n\
+                        #group,false,false,false,false,true\r\n\
+                        #default,_result,,,,\r\n\
+                        ,result,table,_time,_value,host\r\n\
+                        ,,0,2020-01-01T00:00:00Z,0.5,a\r\n\
+                        ,,0,2020-01-01T00:00:10Z,,a\r\n\
+                        ,,1,2020-01-01T00:00:00Z,NaN,\"b,\"\"c\"\"\"\r\n\
+                        \r\n\
+                        #datatype,string,long,boolean,unsignedLong\r\n\
+                        #group,false,false,true,false\r\n\
+                        #default,_result,,,\r\n\
+                        ,result,table,ok,count\r\n\
+                        ,,2,true,18446744073709551615\r\n\
+                        \r\n";
+
+    #[test]
+    fn test_parse_annotated() {
+        let tables = parse_annotated(BODY).unwrap();
+
+        assert_eq!(3, tables.len());
+
+        assert_eq!(vec!["result", "table", "_time", "_value", "host"], tables[0].columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>());
+        assert_eq!(DataType::DateTime, tables[0].columns[2].data_type);
+        assert!(tables[0].columns[4].group);
+        assert_eq!(Some("_result".to_string()), tables[0].columns[0].default);
+        assert_eq!(vec![
+            vec![Value::from("_result"), Value::from(0), Value::from("2020-01-01T00:00:00Z"), Value::from(0.5), Value::from("a")],
+            vec![Value::from("_result"), Value::from(0), Value::from("2020-01-01T00:00:10Z"), Value::Null, Value::from("a")]
+        ], tables[0].records);
+
+        assert_eq!(Value::from("NaN"), tables[1].records[0][3]);
+        assert_eq!(Value::from("b,\"c\""), tables[1].records[0][4]);
+
+        assert_eq!(DataType::Boolean, tables[2].columns[2].data_type);
+        assert_eq!(vec![Value::from("_result"), Value::from(2), Value::Bool(true), Value::from(u64::MAX)], tables[2].records[0]);
+    }
+
+    #[test]
+    fn test_parse_without_annotations() {
+        let tables = parse_annotated(",result,table,_value\n,_result,0,1\n").unwrap();
+
+        assert_eq!(DataType::String, tables[0].columns[2].data_type);
+        assert_eq!(vec![Value::from("_result"), Value::from("0"), Value::from("1")], tables[0].records[0]);
+    }
+
+    #[test]
+    fn test_error_table() {
+        let tables = parse_annotated("#datatype,string,string\n#group,true,true\n#default,,\n,error,reference\n,failed to execute query,897\n").unwrap();
+
+        assert_eq!(Some("failed to execute query"), tables[0].error());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = vec![
+            ("#datatype,long\n,value\n,x\n", 3, 1, "invalid \"x\" value of column \"value\": invalid digit found in string"),
+            ("#datatype,long\n,a,b\n", 2, 1, "expected 2 annotations, found 1"),
+            (",a,b\n,1\n", 2, 1, "expected 3 fields, found 2"),
+            (",a\n,\"1\n", 2, 2, "unterminated quoted field"),
+            (",a\n,\"1\"2\n", 2, 5, "expected delimiter after quoted field"),
+            ("#datatype,int\n,a\n", 2, 1, "unknown data type: int")
+        ];
+
+        for (input, line, column, message) in cases {
+            let e = parse_annotated(input).unwrap_err();
+            assert_eq!((line, column, message.to_string()), (e.line, e.column, e.message), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let body = "name,tags,time,value,host\n\
+                    cpu,host=a,10,0.5,\n\
+                    cpu,host=a,20,1,\n\
+                    cpu,host=b\\=c,10,-1.5,x\n\
+                    name,tags,time,count\n\
+                    mem,,10,18446744073709551615\n\
+                    \n\
+                    error\n\
+                    database not found: db\n\
+                    \n\
+                    name,tags,name,ok\n\
+                    measurements,,1e3,true\n";
+
+        let response = parse_query(body).unwrap();
+
+        assert_eq!(3, response.results.len());
+
+        let series = &response.results[0].series;
+        assert_eq!(3, series.len());
+        assert_eq!(vec![
+            vec![Value::from(10), Value::from(0.5), Value::Null],
+            vec![Value::from(20), Value::from(1), Value::Null]
+        ], series[0].values);
+        assert_eq!("b=c", series[1].tags["host"]);
+        assert_eq!(vec![Value::from(10), Value::from(-1.5), Value::from("x")], series[1].values[0]);
+        assert_eq!(vec!["time", "count"], series[2].columns);
+        assert!(series[2].tags.is_empty());
+        assert_eq!(Value::from(u64::MAX), series[2].values[0][1]);
+
+        assert_eq!(1, response.results[1].statement_id);
+        assert_eq!(Some("database not found: db".to_string()), response.results[1].error);
+
+        assert_eq!(vec![Value::from("1e3"), Value::Bool(true)], response.results[2].series[0].values[0]);
+    }
+
+    #[test]
+    fn test_parse_query_errors() {
+        let cases = vec![
+            ("cpu,,10\n", 1, 1, "expected header"),
+            ("name,tags,time\ncpu,,10,20\n", 2, 1, "expected 3 fields, found 4"),
+            ("name,tags,time\ncpu,host,10\n", 2, 1, "invalid tags: host")
+        ];
+
+        for (input, line, column, message) in cases {
+            let e = parse_query(input).unwrap_err();
+            assert_eq!((line, column, message.to_string()), (e.line, e.column, e.message), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map};
+use ::parser::ParseError;
+
+pub mod csv;
+pub mod msgpack;
+
+pub use serde_json::Value;
+
+/// Parsed response of the `/query` endpoint.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct QueryResponse {
+    /// Results, one per statement of the query.
+    #[serde(default)]
+    pub results: Vec<StatementResult>,
+
+    /// Error of the whole request, if any.
+    #[serde(default)]
+    pub error: Option<String>
+}
+
+/// Result of a single statement.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StatementResult {
+    /// Index of the statement within the query.
+    #[serde(default)]
+    pub statement_id: u32,
+
+    /// Returned series.
+    #[serde(default)]
+    pub series: Vec<Series>,
+
+    /// Informational messages.
+    #[serde(default)]
+    pub messages: Vec<Message>,
+
+    /// Error of the statement, if any.
+    #[serde(default)]
+    pub error: Option<String>,
+
+    /// Whether more data for this statement follows.
+    #[serde(default)]
+    pub partial: bool
+}
+
+/// Series of rows.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Series {
+    /// Measurement name.
+    #[serde(default)]
+    pub name: String,
+
+    /// Map of tags the series is grouped by.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+
+    /// Column names.
+    #[serde(default)]
+    pub columns: Vec<String>,
+
+    /// Rows of values, ordered as `columns`.
+    #[serde(default)]
+    pub values: Vec<Vec<Value>>,
+
+    /// Whether more rows for this series follow.
+    #[serde(default)]
+    pub partial: bool
+}
+
+/// Value of the `time` column.
+///
+/// Server returns time as RFC3339 string, unless `epoch` precision is given to the query, in which
+/// case time is returned as integer number of epoch units. `Time` accepts both forms, so it could
+/// be used as a type of row's time field regardless of the query `epoch`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Time {
+    /// Integer number of epoch units.
+    Epoch(i64),
+    /// RFC3339 formatted string.
+    Rfc3339(String)
+}
+
+/// Informational message of a statement.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Message {
+    /// Level, e.g. `warning`.
+    pub level: String,
+
+    /// Message text.
+    pub text: String
+}
+
+impl QueryResponse {
+    /// Parses JSON body of the `/query` endpoint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use influent::query::QueryResponse;
+    ///
+    /// let response = QueryResponse::from_json("{\"results\":[{\"statement_id\":0}]}").unwrap();
+    ///
+    /// assert_eq!(0, response.results[0].statement_id);
+    /// ```
+    pub fn from_json(body: &str) -> Result<QueryResponse, serde_json::Error> {
+        serde_json::from_str(body)
+    }
+
+    /// Parses CSV body of the `/query` endpoint.
+    ///
+    /// See `csv::parse_query` for details.
+    pub fn from_csv(body: &str) -> Result<QueryResponse, ParseError> {
+        csv::parse_query(body)
+    }
+
+    /// Parses MessagePack body of the `/query` endpoint.
+    ///
+    /// See `msgpack::parse_query` for details.
+    pub fn from_msgpack(body: &[u8]) -> Result<QueryResponse, String> {
+        msgpack::parse_query(body)
+    }
+}
+
+impl StatementResult {
+    /// Deserializes rows of all series of the statement into `T`.
+    ///
+    /// See `Series::rows` for details.
+    pub fn rows<T>(&self) -> Result<Vec<T>, serde_json::Error> where T: DeserializeOwned {
+        let mut rows = Vec::new();
+
+        for series in &self.series {
+            rows.extend(series.rows()?);
+        }
+
+        Ok(rows)
+    }
+}
+
+impl Series {
+    /// Returns index of the column with given name.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name)
+    }
+
+    /// Deserializes each row of the series into `T`.
+    ///
+    /// Every row is mapped by column name to the field of `T`. Tags of the series are mapped too,
+    /// unless there is a column with the same name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate influent;
+    /// #[macro_use]
+    /// extern crate serde_derive;
+    ///
+    /// use influent::query::{QueryResponse, Time};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Row {
+    ///     time: Time,
+    ///     host: String,
+    ///     value: f64
+    /// }
+    ///
+    /// # fn main() {
+    /// let body = "{\"results\":[{\"series\":[{\"name\":\"cpu\",\"tags\":{\"host\":\"a\"},\"columns\":[\"time\",\"value\"],\"values\":[[1434055562,0.5]]}]}]}";
+    /// let response = QueryResponse::from_json(body).unwrap();
+    /// let rows: Vec<Row> = response.results[0].series[0].rows().unwrap();
+    ///
+    /// assert_eq!(Time::Epoch(1434055562), rows[0].time);
+    /// assert_eq!("a", rows[0].host);
+    /// assert_eq!(0.5, rows[0].value);
+    /// # }
+    /// ```
+    pub fn rows<T>(&self) -> Result<Vec<T>, serde_json::Error> where T: DeserializeOwned {
+        self.values.iter().map(|values| {
+            let mut row = Map::new();
+
+            for (tag, value) in &self.tags {
+                row.insert(tag.clone(), Value::String(value.clone()));
+            }
+
+            for (column, value) in self.columns.iter().zip(values) {
+                row.insert(column.clone(), value.clone());
+            }
+
+            serde_json::from_value(Value::Object(row))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QueryResponse, Time, Value};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Row {
+        time: Time,
+        tag: String,
+        value: Option<i64>
+    }
+
+    #[test]
+    fn test_from_json() {
+        let body = "{\"results\":[{\"statement_id\":0,\"series\":[{\"name\":\"sut\",\"tags\":{\"host\":\"a\"},\"columns\":[\"time\",\"value\"],\"values\":[[\"2015-06-11T20:46:02Z\",10]]}]}]}";
+        let response = QueryResponse::from_json(body).unwrap();
+
+        assert_eq!(None, response.error);
+        assert_eq!(1, response.results.len());
+
+        let series = &response.results[0].series[0];
+
+        assert_eq!("sut", series.name);
+        assert_eq!(Some(&"a".to_string()), series.tags.get("host"));
+        assert_eq!(Some(1), series.column("value"));
+        assert_eq!(Value::from(10), series.values[0][1]);
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        let body = "{\"results\":[{\"statement_id\":0,\"messages\":[{\"level\":\"warning\",\"text\":\"deprecated\"}]},{\"statement_id\":1,\"error\":\"database not found: x\"}]}";
+        let response = QueryResponse::from_json(body).unwrap();
+
+        assert_eq!("deprecated", response.results[0].messages[0].text);
+        assert!(response.results[0].series.is_empty());
+        assert_eq!(1, response.results[1].statement_id);
+        assert_eq!(Some("database not found: x".to_string()), response.results[1].error);
+
+        let response = QueryResponse::from_json("{\"error\":\"error parsing query\"}").unwrap();
+
+        assert_eq!(Some("error parsing query".to_string()), response.error);
+        assert!(response.results.is_empty());
+    }
+
+    #[test]
+    fn test_rows() {
+        let body = "{\"results\":[{\"statement_id\":0,\"series\":[{\"name\":\"sut\",\"tags\":{\"tag\":\"a\"},\"columns\":[\"time\",\"value\"],\"values\":[[\"2015-06-11T20:46:02Z\",10],[\"2015-06-11T20:46:03Z\",null]]},{\"name\":\"sut\",\"tags\":{\"tag\":\"b\"},\"columns\":[\"time\",\"value\"],\"values\":[[1434055564,20]]}]}]}";
+        let response = QueryResponse::from_json(body).unwrap();
+        let rows: Vec<Row> = response.results[0].rows().unwrap();
+
+        assert_eq!(vec![
+            Row { time: Time::Rfc3339("2015-06-11T20:46:02Z".to_string()), tag: "a".to_string(), value: Some(10) },
+            Row { time: Time::Rfc3339("2015-06-11T20:46:03Z".to_string()), tag: "a".to_string(), value: None },
+            Row { time: Time::Epoch(1434055564), tag: "b".to_string(), value: Some(20) }
+        ], rows);
+    }
+
+    #[test]
+    fn test_rows_unsigned() {
+        #[derive(Deserialize)]
+        struct Counter {
+            value: u64
+        }
+
+        let body = "{\"results\":[{\"statement_id\":0,\"series\":[{\"name\":\"sut\",\"columns\":[\"time\",\"value\"],\"values\":[[1,18446744073709551615]]}]}]}";
+        let response = QueryResponse::from_json(body).unwrap();
+        let rows: Vec<Counter> = response.results[0].rows().unwrap();
+
+        assert_eq!(Value::from(u64::MAX), response.results[0].series[0].values[0][1]);
+        assert_eq!(u64::MAX, rows[0].value);
+    }
+
+    #[test]
+    fn test_rows_type_mismatch() {
+        let body = "{\"results\":[{\"series\":[{\"name\":\"sut\",\"columns\":[\"time\",\"tag\",\"value\"],\"values\":[[1,\"a\",\"ten\"]]}]}]}";
+        let response = QueryResponse::from_json(body).unwrap();
+
+        assert!(response.results[0].series[0].rows::<Row>().is_err());
+    }
+}
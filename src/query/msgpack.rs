@@ -0,0 +1,230 @@
+use base64;
+use rmpv::{self, decode};
+use serde_json::{self, Map, Number};
+use super::{QueryResponse, Value};
+
+/// Extension type of the time values, written by InfluxDB.
+const TIME_EXT: i8 = 5;
+
+/// Extension type of the standard MessagePack timestamps.
+const TIMESTAMP_EXT: i8 = -1;
+
+/// Parses MessagePack response of the InfluxDB 1.x `/query` endpoint.
+///
+/// Response has the same structure as the JSON one. Time values are decoded into RFC3339 strings,
+/// as JSON responses contain them, and binary values into base64 strings.
+///
+/// # Examples
+///
+/// ```
+/// extern crate influent;
+/// extern crate rmpv;
+///
+/// use influent::query::msgpack::parse_query;
+/// use influent::query::Value;
+/// use rmpv::Value as Msgpack;
+///
+/// # fn main() {
+/// let series = Msgpack::Map(vec![
+///     ("name".into(), "cpu".into()),
+///     ("columns".into(), Msgpack::Array(vec!["time".into(), "value".into()])),
+///     ("values".into(), Msgpack::Array(vec![Msgpack::Array(vec![Msgpack::Ext(5, vec![0, 0, 0, 0, 85, 121, 243, 138, 0, 0, 0, 0]), 0.5.into()])]))
+/// ]);
+/// let result = Msgpack::Map(vec![("statement_id".into(), 0.into()), ("series".into(), Msgpack::Array(vec![series]))]);
+/// let response = Msgpack::Map(vec![("results".into(), Msgpack::Array(vec![result]))]);
+///
+/// let mut body = Vec::new();
+/// rmpv::encode::write_value(&mut body, &response).unwrap();
+///
+/// let response = parse_query(&body).unwrap();
+///
+/// assert_eq!(vec![Value::from("2015-06-11T20:46:02Z"), Value::from(0.5)], response.results[0].series[0].values[0]);
+/// # }
+/// ```
+pub fn parse_query(body: &[u8]) -> Result<QueryResponse, String> {
+    let mut rd = body;
+    let value = decode::read_value(&mut rd).map_err(|e| format!("could not decode msgpack: {}", e))?;
+
+    if !rd.is_empty() {
+        return Err(format!("unexpected {} bytes after the response", rd.len()));
+    }
+
+    serde_json::from_value(json(value)?).map_err(|e| e.to_string())
+}
+
+fn json(value: rmpv::Value) -> Result<Value, String> {
+    Ok(match value {
+        rmpv::Value::Nil => Value::Null,
+        rmpv::Value::Boolean(b) => Value::Bool(b),
+        rmpv::Value::Integer(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(n), _) => Value::from(n),
+            (None, Some(n)) => Value::from(n),
+            _ => return Err(format!("invalid integer: {}", n))
+        },
+        rmpv::Value::F32(f) => float(f64::from(f)),
+        rmpv::Value::F64(f) => float(f),
+        rmpv::Value::String(s) => match s.into_str() {
+            Some(s) => Value::String(s),
+            None => return Err("invalid utf-8 string".to_string())
+        },
+        rmpv::Value::Binary(bytes) => Value::String(base64::encode(&bytes)),
+        rmpv::Value::Array(values) => Value::Array(values.into_iter().map(json).collect::<Result<_, _>>()?),
+        rmpv::Value::Map(pairs) => {
+            let mut map = Map::new();
+
+            for (key, value) in pairs {
+                let key = match key {
+                    rmpv::Value::String(s) => s.into_str().ok_or_else(|| "invalid utf-8 key".to_string())?,
+                    key => return Err(format!("invalid key: {}", key))
+                };
+
+                map.insert(key, json(value)?);
+            }
+
+            Value::Object(map)
+        },
+        rmpv::Value::Ext(kind, data) => Value::String(time(kind, &data)?)
+    })
+}
+
+/// JSON numbers could not be NaN or infinite, so such values are strings, as InfluxDB writes them
+/// in CSV responses.
+fn float(f: f64) -> Value {
+    match Number::from_f64(f) {
+        Some(n) => Value::Number(n),
+        None if f.is_nan() => Value::from("NaN"),
+        None if f > 0.0 => Value::from("+Inf"),
+        None => Value::from("-Inf")
+    }
+}
+
+/// Decodes time extension into RFC3339 string.
+fn time(kind: i8, data: &[u8]) -> Result<String, String> {
+    let mut be = [0u8; 8];
+
+    let (secs, nanos) = match (kind, data.len()) {
+        (TIME_EXT, 12) => {
+            be.copy_from_slice(&data[..8]);
+            (i64::from_be_bytes(be), u32_be(&data[8..]))
+        },
+        (TIMESTAMP_EXT, 4) => (i64::from(u32_be(data)), 0),
+        (TIMESTAMP_EXT, 8) => {
+            be.copy_from_slice(data);
+            let n = u64::from_be_bytes(be);
+            ((n & 0x3_ffff_ffff) as i64, (n >> 34) as u32)
+        },
+        (TIMESTAMP_EXT, 12) => {
+            be.copy_from_slice(&data[4..]);
+            (i64::from_be_bytes(be), u32_be(&data[..4]))
+        },
+        _ => return Err(format!("unknown extension type {} of {} bytes", kind, data.len()))
+    };
+
+    Ok(rfc3339(secs, nanos))
+}
+
+fn u32_be(data: &[u8]) -> u32 {
+    let mut be = [0u8; 4];
+    be.copy_from_slice(&data[..4]);
+    u32::from_be_bytes(be)
+}
+
+/// Formats UTC time as RFC3339 with nanoseconds, omitting trailing zeros of the fraction.
+fn rfc3339(secs: i64, nanos: u32) -> String {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+
+    // converts days since epoch to the civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let mut s = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60);
+
+    if nanos > 0 {
+        let fraction = format!("{:09}", nanos);
+        s.push('.');
+        s.push_str(fraction.trim_end_matches('0'));
+    }
+
+    s.push('Z');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_query, rfc3339, float};
+    use ::query::Value;
+    use rmpv::{self, Value as Msgpack};
+
+    fn encode(value: Msgpack) -> Vec<u8> {
+        let mut body = Vec::new();
+        rmpv::encode::write_value(&mut body, &value).unwrap();
+        body
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!("1970-01-01T00:00:00Z", rfc3339(0, 0));
+        assert_eq!("2015-06-11T20:46:02.5Z", rfc3339(1434055562, 500_000_000));
+        assert_eq!("2000-02-29T23:59:59.000000001Z", rfc3339(951868799, 1));
+        assert_eq!("1969-12-31T23:59:59Z", rfc3339(-1, 0));
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let series = Msgpack::Map(vec![
+            ("name".into(), "cpu".into()),
+            ("tags".into(), Msgpack::Map(vec![("host".into(), "a".into())])),
+            ("columns".into(), Msgpack::Array(vec!["time".into(), "value".into(), "ok".into(), "raw".into()])),
+            ("values".into(), Msgpack::Array(vec![
+                Msgpack::Array(vec![Msgpack::Ext(-1, vec![0x55, 0x79, 0xf3, 0x8a]), (-1i64).into(), true.into(), Msgpack::Binary(vec![1, 2])]),
+                Msgpack::Array(vec![Msgpack::Ext(-1, vec![0, 0, 0, 1, 0, 0, 0, 0, 0x55, 0x79, 0xf3, 0x8a]), u64::MAX.into(), Msgpack::Nil, Msgpack::F64(f64::NAN)])
+            ])),
+            ("partial".into(), true.into())
+        ]);
+        let results = Msgpack::Array(vec![
+            Msgpack::Map(vec![
+                ("statement_id".into(), 0.into()),
+                ("messages".into(), Msgpack::Array(vec![Msgpack::Map(vec![("level".into(), "warning".into()), ("text".into(), "deprecated".into())])])),
+                ("series".into(), Msgpack::Array(vec![series]))
+            ]),
+            Msgpack::Map(vec![("error".into(), "database not found: db".into())])
+        ]);
+
+        let response = parse_query(&encode(Msgpack::Map(vec![("results".into(), results)]))).unwrap();
+
+        let series = &response.results[0].series[0];
+        assert_eq!("a", series.tags["host"]);
+        assert!(series.partial);
+        assert_eq!(vec![Value::from("2015-06-11T20:46:02Z"), Value::from(-1), Value::Bool(true), Value::from("AQI=")], series.values[0]);
+        assert_eq!(vec![Value::from("2015-06-11T20:46:02.000000001Z"), Value::from(u64::MAX), Value::Null, Value::from("NaN")], series.values[1]);
+        assert_eq!("deprecated", response.results[0].messages[0].text);
+        assert_eq!(Some("database not found: db".to_string()), response.results[1].error);
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(Value::from(0.5), float(0.5));
+        assert_eq!(Value::from("NaN"), float(f64::NAN));
+        assert_eq!(Value::from("+Inf"), float(f64::INFINITY));
+        assert_eq!(Value::from("-Inf"), float(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn test_parse_query_errors() {
+        assert!(parse_query(&[0xc1]).is_err());
+        assert!(parse_query(&encode(Msgpack::Map(vec![(1.into(), 2.into())]))).is_err());
+        assert!(parse_query(&encode(Msgpack::Ext(3, vec![1]))).is_err());
+
+        let mut body = encode(Msgpack::Map(vec![]));
+        body.push(0);
+        assert!(parse_query(&body).is_err());
+    }
+}
@@ -18,30 +18,58 @@ impl LineSerializer {
     /// let serializer = LineSerializer::new();
     /// let mut measurement = Measurement::new("key");
     ///
-    /// measurement.add_field("field", Value::String("value"));
+    /// measurement.add_field("field", Value::String("value".into()));
     /// measurement.add_tag("tag", "value");
     ///
     /// assert_eq!("key,tag=value field=\"value\"", serializer.serialize(&measurement));
     /// ```
     pub fn new() -> LineSerializer {
-        LineSerializer::default()
+        LineSerializer
     }
 }
 
-fn escape(s: &str) -> String {
-    s
-        .replace(" ", "\\ ")
-        .replace(",", "\\,")
+/// Characters which must be escaped in the measurement name.
+const NAME: &[char] = &[',', ' '];
+
+/// Characters which must be escaped in the tag keys, tag values and field keys.
+const KEY: &[char] = &[',', '=', ' '];
+
+/// Escapes characters of `special` with backslash.
+///
+/// Backslash itself is escaped only when it would otherwise escape the next character, or when it ends
+/// the string, so other backslashes are sent as is.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        } else if c == '\\' {
+            match chars.peek() {
+                Some(&next) if next != '\\' && !special.contains(&next) => {},
+                _ => escaped.push('\\')
+            }
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
 }
 
 fn as_string(s: &str) -> String {
-    format!("\"{}\"", s.replace("\"", "\\\""))
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\""))
 }
 
 fn as_integer(i: &i64) -> String {
     format!("{}i", i)
 }
 
+fn as_uinteger(u: &u64) -> String {
+    format!("{}u", u)
+}
+
 fn as_float(f: &f64) -> String {
     f.to_string()
 }
@@ -52,25 +80,26 @@ fn as_boolean(b: &bool) -> String {
 
 impl Serializer for LineSerializer {
     fn serialize(&self, measurement: &Measurement) -> String {
-        let mut line = vec![escape(measurement.key)];
+        let mut line = vec![escape(&measurement.key, NAME)];
 
         for (tag, value) in &measurement.tags {
             line.push(",".to_string());
-            line.push(escape(tag));
+            line.push(escape(tag, KEY));
             line.push("=".to_string());
-            line.push(escape(value));
+            line.push(escape(value, KEY));
         }
 
         let mut was_spaced = false;
 
         for (field, value) in &measurement.fields {
             line.push({if !was_spaced { was_spaced = true; " " } else { "," }}.to_string());
-            line.push(escape(field));
+            line.push(escape(field, KEY));
             line.push("=".to_string());
 
             match *value {
-                Value::String(s)  => line.push(as_string(s)),
+                Value::String(ref s)  => line.push(as_string(s)),
                 Value::Integer(ref i) => line.push(as_integer(i)),
+                Value::UInteger(ref u) => line.push(as_uinteger(u)),
                 Value::Float(ref f)   => line.push(as_float(f)),
                 Value::Boolean(ref b) => line.push(as_boolean(b))
             };
@@ -87,9 +116,12 @@ impl Serializer for LineSerializer {
 
 #[cfg(test)]
 mod tests {
-    use super::{as_boolean, as_string, as_integer, as_float, escape, LineSerializer};
+    use super::{as_boolean, as_string, as_integer, as_uinteger, as_float, escape, LineSerializer, NAME, KEY};
     use ::serializer::Serializer;
+    use ::parser::Parser;
+    use ::parser::line::LineParser;
     use ::measurement::{Measurement, Value};
+    use proptest::prelude::*;
 
     #[test]
     fn test_as_boolean() {
@@ -99,7 +131,8 @@ mod tests {
 
     #[test]
     fn test_as_string() {
-        assert_eq!("\"\\\"hello\\\"\"", as_string(&"\"hello\""));
+        assert_eq!("\"\\\"hello\\\"\"", as_string("\"hello\""));
+        assert_eq!("\"C:\\\\dir\\\\\"", as_string("C:\\dir\\"));
     }
 
     #[test]
@@ -111,6 +144,13 @@ mod tests {
     }
 
     #[test]
+    fn test_as_uinteger() {
+        assert_eq!("0u", as_uinteger(&0u64));
+        assert_eq!("18446744073709551615u", as_uinteger(&u64::MAX));
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
     fn test_as_float() {
         assert_eq!("1", as_float(&1f64));
         assert_eq!("1", as_float(&1.0f64));
@@ -120,9 +160,20 @@ mod tests {
 
     #[test]
     fn test_escape() {
-        assert_eq!("\\ ", escape(" "));
-        assert_eq!("\\,", escape(","));
-        assert_eq!("hello\\,\\ gobwas", escape("hello, gobwas"));
+        assert_eq!("\\ ", escape(" ", NAME));
+        assert_eq!("\\,", escape(",", NAME));
+        assert_eq!("a=b", escape("a=b", NAME));
+        assert_eq!("a\\=b", escape("a=b", KEY));
+        assert_eq!("hello\\,\\ gobwas", escape("hello, gobwas", KEY));
+    }
+
+    #[test]
+    fn test_escape_backslash() {
+        assert_eq!("a\\b", escape("a\\b", KEY));
+        assert_eq!("a\\\\", escape("a\\", KEY));
+        assert_eq!("a\\\\\\,", escape("a\\,", KEY));
+        assert_eq!("a\\\\\\b", escape("a\\\\b", KEY));
+        assert_eq!("a\\=", escape("a\\=", NAME));
     }
 
     #[test]
@@ -130,20 +181,21 @@ mod tests {
         let serializer = LineSerializer::new();
         let mut measurement = Measurement::new("key");
 
-        measurement.add_field("s", Value::String("string"));
+        measurement.add_field("s", Value::String("string".into()));
         measurement.add_field("i", Value::Integer(10));
+        measurement.add_field("u", Value::UInteger(10));
         measurement.add_field("f", Value::Float(10f64));
         measurement.add_field("b", Value::Boolean(false));
 
         measurement.add_tag("tag", "value");
 
-        measurement.add_field("one, two", Value::String("three"));
+        measurement.add_field("one, two", Value::String("three".into()));
         measurement.add_tag("one ,two", "three, four");
 
 
         measurement.set_timestamp(10);
 
-        assert_eq!("key,one\\ \\,two=three\\,\\ four,tag=value b=f,f=10,i=10i,one\\,\\ two=\"three\",s=\"string\" 10", serializer.serialize(&measurement));
+        assert_eq!("key,one\\ \\,two=three\\,\\ four,tag=value b=f,f=10,i=10i,one\\,\\ two=\"three\",s=\"string\",u=10u 10", serializer.serialize(&measurement));
     }
 
     #[test]
@@ -151,14 +203,52 @@ mod tests {
         let serializer = LineSerializer::new();
         let mut measurement = Measurement::new("key");
 
-        measurement.add_field("s", Value::String("string"));
+        measurement.add_field("s", Value::String("string".into()));
 
         measurement.set_timestamp(1434055562000000000);
 
         assert_eq!("key s=\"string\" 1434055562000000000", serializer.serialize(&measurement));
     }
-}
 
+    const IDENTIFIER: &str = "[a-zA-Z0-9_ ,=\\\\\"ü]{1,8}";
+
+    fn value() -> impl Strategy<Value=Value<'static>> {
+        prop_oneof![
+            "[a-z \\\\\"\n,=]{0,8}".prop_map(|s| Value::String(s.into())),
+            any::<i64>().prop_map(Value::Integer),
+            any::<u64>().prop_map(Value::UInteger),
+            (-1e300f64..1e300f64).prop_map(Value::Float),
+            any::<bool>().prop_map(Value::Boolean)
+        ]
+    }
+
+    fn measurement() -> impl Strategy<Value=Measurement<'static>> {
+        let tags = prop::collection::btree_map(IDENTIFIER, IDENTIFIER, 0..3);
+        let fields = prop::collection::btree_map(IDENTIFIER, value(), 1..4);
+
+        (IDENTIFIER, tags, fields, any::<Option<i64>>()).prop_map(|(key, tags, fields, timestamp)| {
+            let mut measurement = Measurement::new(key);
 
+            for (tag, value) in tags {
+                measurement.add_tag(tag, value);
+            }
 
+            for (field, value) in fields {
+                measurement.add_field(field, value);
+            }
 
+            measurement.timestamp = timestamp;
+            measurement
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_serialized(measurement in measurement()) {
+            let line = LineSerializer::new().serialize(&measurement);
+            let parsed = LineParser::new().parse(&line).unwrap();
+
+            prop_assert_eq!(vec![measurement], parsed);
+        }
+    }
+}
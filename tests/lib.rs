@@ -6,32 +6,27 @@ use influent::create_client;
 use influent::client::{Client, Credentials};
 use influent::client::http::HttpClient;
 use influent::measurement::{Measurement, Value};
+use influent::query::Value as Json;
 use futures::Future;
-use std::sync::Arc;
 
-fn before<'a>() -> HttpClient<'a> {
+fn before() -> HttpClient {
 	let credentials = Credentials {
         username: "gobwas",
         password: "xxxx",
         database: "test"
     };
 
-    let client = Arc::new(create_client(credentials, vec!["http://localhost:8086"]));
-
-    {
-        let client = client.clone();
-        let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
-        rt.block_on(
-            client.query("drop database test".to_string(), None).then(move |_| {
-                client.query("create database test".to_string(), None)
-            }).map(|_| ()).map_err(|_| ())
-        ).unwrap();
-    }
-
-    if let Ok(client) = Arc::try_unwrap(client) {
-        return client
-    }
-    panic!("wtf")
+    let client = create_client(credentials, vec!["http://localhost:8086"]).unwrap();
+    let recreate = client.clone();
+
+    let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
+    rt.block_on(
+        client.query("drop database test".to_string(), None).then(move |_| {
+            recreate.query("create database test".to_string(), None)
+        }).map(|_| ()).map_err(|_| ())
+    ).unwrap();
+
+    client
 }
 
 #[test]
@@ -40,11 +35,11 @@ fn test_write_measurement() {
 
     let mut measurement = Measurement::new("sut");
 
-    measurement.add_field("string", Value::String("string"));
+    measurement.add_field("string", Value::String("string".into()));
     measurement.add_field("integer", Value::Integer(10));
     measurement.add_field("float", Value::Float(10f64));
     measurement.add_field("boolean", Value::Boolean(false));
-    measurement.add_field("with, comma", Value::String("comma, with"));
+    measurement.add_field("with, comma", Value::String("comma, with".into()));
 
     measurement.add_tag("tag", "value");
     measurement.add_tag("tag, with comma", "three, four");
@@ -60,3 +55,26 @@ fn test_write_measurement() {
         assert_eq!(fixture, res);
     }).map_err(|e| println!("{:?}", e))).unwrap();
 }
+
+#[test]
+fn test_query_results() {
+    let client = before();
+
+    let mut measurement = Measurement::new("sut");
+
+    measurement.add_field("integer", Value::Integer(10));
+    measurement.add_tag("tag", "value");
+    measurement.set_timestamp(1_434_055_562_000_000_000);
+
+    let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+    let response = rt.block_on(client.write_one(measurement, None).then(move |_| {
+        client.query_results("select * from \"sut\"".to_string(), None)
+    })).unwrap();
+
+    let series = &response.results[0].series[0];
+
+    assert_eq!("sut", series.name);
+    assert_eq!(vec!["time", "integer", "tag"], series.columns);
+    assert_eq!(vec![Json::from("2015-06-11T20:46:02Z"), Json::from(10), Json::from("value")], series.values[0]);
+}
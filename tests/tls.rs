@@ -0,0 +1,124 @@
+#![cfg(feature = "tls")]
+
+extern crate influent;
+extern crate native_tls;
+extern crate tokio;
+
+use influent::hurl::{Hurl, Request, Method, HurlError, ErrorKind};
+use influent::hurl::hyper::{HyperHurl, HyperOptions};
+use influent::hurl::tls::{ClientCertificate, TlsOptions};
+use native_tls::{Identity, TlsAcceptor};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+
+/// Starts HTTPS server with the test certificate, which responds with 204 to every request.
+fn serve() -> String {
+    let identity = Identity::from_pkcs8(include_bytes!("tls/server.pem"), include_bytes!("tls/server.key")).unwrap();
+    let acceptor = Arc::new(TlsAcceptor::new(identity).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let acceptor = acceptor.clone();
+
+            thread::spawn(move || {
+                // handshake fails for clients which do not trust the certificate
+                let mut stream = match acceptor.accept(stream.unwrap()) {
+                    Ok(stream) => BufReader::new(stream),
+                    Err(_) => return
+                };
+
+                let mut line = String::new();
+
+                while stream.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                stream.get_mut().write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").unwrap();
+            });
+        }
+    });
+
+    format!("https://localhost:{}/ping", port)
+}
+
+fn ping(tls: TlsOptions) -> Result<u16, HurlError> {
+    let url = serve();
+    let hurl = HyperHurl::with_options(HyperOptions { tls, ..HyperOptions::default() })?;
+
+    let request = Request {
+        url: &url,
+        method: Method::GET,
+        auth: None,
+        query: None,
+        headers: None,
+        body: None,
+        connect_timeout: None
+    };
+
+    let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
+    rt.block_on(hurl.request(request)).map(|resp| resp.status)
+}
+
+#[test]
+fn test_https_with_ca() {
+    let tls = TlsOptions {
+        ca_certificates: vec![include_bytes!("tls/ca.pem").to_vec()],
+        ..TlsOptions::default()
+    };
+
+    assert_eq!(204, ping(tls).unwrap());
+}
+
+#[test]
+fn test_https_with_ca_file() {
+    let mut tls = TlsOptions::default();
+    tls.add_ca_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/ca.pem")).unwrap();
+
+    assert_eq!(204, ping(tls).unwrap());
+}
+
+// test server does not request client certificates, so only loading of the certificate is checked
+#[test]
+fn test_https_with_client_certificate() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/");
+
+    let tls = TlsOptions {
+        ca_certificates: vec![include_bytes!("tls/ca.pem").to_vec()],
+        client_certificate: Some(ClientCertificate::from_files(format!("{}client.pem", dir), format!("{}client.key", dir)).unwrap()),
+        ..TlsOptions::default()
+    };
+
+    assert_eq!(204, ping(tls).unwrap());
+}
+
+#[test]
+fn test_https_skip_verify() {
+    let tls = TlsOptions {
+        skip_verify: true,
+        ..TlsOptions::default()
+    };
+
+    assert_eq!(204, ping(tls).unwrap());
+}
+
+#[test]
+fn test_https_untrusted() {
+    assert_eq!(ErrorKind::Tls, ping(TlsOptions::default()).unwrap_err().kind());
+}
+
+#[test]
+fn test_invalid_ca() {
+    let tls = TlsOptions {
+        ca_certificates: vec![b"not a certificate".to_vec()],
+        ..TlsOptions::default()
+    };
+
+    match HyperHurl::with_options(HyperOptions { tls, ..HyperOptions::default() }) {
+        Err(e) => assert_eq!(ErrorKind::Tls, e.kind()),
+        Ok(_) => panic!("expected invalid CA to be rejected")
+    }
+}